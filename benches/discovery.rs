@@ -0,0 +1,97 @@
+/*!
+Times how long pyn takes to find the packages of a generated 600 package Yarn
+workspace, with and without the discovery cache. Each package has sources and
+a node_modules to get past, and there's a docs site outside of the workspace
+globs that shouldn't be searched at all.
+
+Run with `cargo bench --bench discovery`.
+*/
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+const PACKAGES: usize = 600;
+const RUNS: u32 = 20;
+
+fn write(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+fn generate_workspace(root: &Path) {
+    write(
+        &root.join("package.json"),
+        r#"{ "name": "generated", "private": true, "workspaces": ["packages/*/*"] }"#,
+    );
+    write(&root.join("yarn.lock"), "");
+    for idx in 0..PACKAGES {
+        let dir = root.join(format!("packages/group-{}/pkg-{}", idx % 10, idx));
+        let deps = match idx {
+            0 => String::new(),
+            _ => format!(r#", "dependencies": {{ "pkg-{}": "^1.0.0" }}"#, idx - 1),
+        };
+        write(
+            &dir.join("package.json"),
+            &format!(r#"{{ "name": "pkg-{}", "version": "1.0.0"{} }}"#, idx, deps),
+        );
+        for file in 0..5 {
+            write(&dir.join(format!("src/nested/file-{}.js", file)), "");
+        }
+        for dep in 0..10 {
+            write(
+                &dir.join(format!("node_modules/dep-{}/package.json", dep)),
+                &format!(r#"{{ "name": "dep-{}" }}"#, dep),
+            );
+        }
+    }
+    for page in 0..2000 {
+        write(
+            &root.join(format!("docs/pages/section-{}/page-{}.md", page % 50, page)),
+            "",
+        );
+    }
+}
+
+/// The average time `pyn workspaces` takes, which is mostly finding the packages
+fn time_workspaces(root: &Path) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let status = Command::new(env!("CARGO_BIN_EXE_pyn"))
+            .args(["workspaces", "--json"])
+            .current_dir(root)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let dir = tempfile::Builder::new()
+        .prefix("pyn-bench-")
+        .tempdir()
+        .unwrap();
+    let root = dir.path();
+    generate_workspace(root);
+
+    let uncached = time_workspaces(root);
+    println!(
+        "Found {} packages in {:.1}ms without the cache",
+        PACKAGES,
+        uncached.as_secs_f64() * 1000.0
+    );
+    // the cache is only written once there's a node_modules
+    fs::create_dir(root.join("node_modules")).unwrap();
+    time_workspaces(root);
+    let cached = time_workspaces(root);
+    println!(
+        "Found {} packages in {:.1}ms with the cache",
+        PACKAGES,
+        cached.as_secs_f64() * 1000.0
+    );
+}
@@ -0,0 +1,176 @@
+use crate::{
+    lockfile::Lockfile,
+    project::{PackageKey, Project},
+    range::Range,
+    PackageName,
+};
+use semver::Version;
+
+#[derive(Debug, PartialEq)]
+pub enum Mismatch {
+    /// Nothing in the lockfile is resolved for the range
+    Missing,
+    /// The lockfile has the dependency at a version outside of the range
+    Unsatisfied(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Drift {
+    pub package: PackageKey,
+    pub dependency: PackageName,
+    pub range: String,
+    pub mismatch: Mismatch,
+}
+
+fn satisfies(range: &Range, version: &str) -> bool {
+    Version::parse(version)
+        .map(|version| range.satisfies(&version))
+        .unwrap_or(false)
+}
+
+/**
+Finds every dependency range in the workspace that isn't satisfied by the
+lockfile. Links to other workspace packages and specifiers that aren't semver
+ranges (tags, git urls, aliases and so on) can't be checked without the
+network so they're skipped.
+*/
+pub fn find_drift(project: &Project, lockfile: &Lockfile) -> Vec<Drift> {
+    let workspace_names: Vec<&PackageName> = project
+        .iter()
+        .filter_map(|pkg| pkg.pkg_json.name.as_ref())
+        .collect();
+    let mut drift = vec![];
+    let mut packages: Vec<_> = project.iter().collect();
+    packages.sort_by(|a, b| a.key.cmp(&b.key));
+    for pkg in packages {
+        let importer = lockfile.importer(project, pkg);
+        let pkg_json = &pkg.pkg_json;
+        let normal_deps = pkg_json
+            .iter_normal_deps()
+            .flatten()
+            .map(|dep| (dep, false));
+        let peer_deps = pkg_json.peer_dependencies.iter().map(|dep| (dep, true));
+        for ((name, raw_range), is_peer) in normal_deps.chain(peer_deps) {
+            if workspace_names.contains(&name) {
+                continue;
+            }
+            let range: Range = match raw_range.parse() {
+                Ok(range) => range,
+                Err(_) => continue,
+            };
+            let resolved = importer
+                .and_then(|importer| importer.get(name))
+                .map(|&idx| &lockfile.packages[idx]);
+            let mismatch = match resolved {
+                Some(locked) if satisfies(&range, &locked.version) => continue,
+                Some(locked) => Mismatch::Unsatisfied(locked.version.clone()),
+                // peers are provided by whatever depends on the package so any
+                // copy in the lockfile will do, and none at all isn't drift
+                None if is_peer => {
+                    let copies: Vec<_> = lockfile
+                        .packages
+                        .iter()
+                        .filter(|locked| &locked.name == name)
+                        .collect();
+                    if copies.is_empty()
+                        || copies
+                            .iter()
+                            .any(|locked| satisfies(&range, &locked.version))
+                    {
+                        continue;
+                    }
+                    Mismatch::Unsatisfied(copies[0].version.clone())
+                }
+                None => Mismatch::Missing,
+            };
+            drift.push(Drift {
+                package: pkg.key.clone(),
+                dependency: name.clone(),
+                range: raw_range.clone(),
+                mismatch,
+            });
+        }
+    }
+    drift
+}
+
+pub fn check(project: &Project) -> anyhow::Result<()> {
+    let lockfile = Lockfile::load(project)?;
+    let drift = find_drift(project, &lockfile);
+    let lockfile_name = project.manager.lockfile_name();
+    for drift in &drift {
+        match &drift.mismatch {
+            Mismatch::Missing => println!(
+                "{}: {}@{} is not in {}",
+                drift.package, drift.dependency, drift.range, lockfile_name
+            ),
+            Mismatch::Unsatisfied(version) => println!(
+                "{}: {}@{} is locked at {}",
+                drift.package, drift.dependency, drift.range, version
+            ),
+        }
+    }
+    if !drift.is_empty() {
+        anyhow::bail!(
+            "{} dependencies don't match {}, run {} install to update it",
+            drift.len(),
+            lockfile_name,
+            project.manager
+        );
+    }
+    println!("Every dependency matches {}", lockfile_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn fixture(name: &str) -> Project {
+        let mut dir = env::current_dir().unwrap();
+        dir.push("fixtures");
+        dir.push(name);
+        Project::find(&dir, None).unwrap()
+    }
+
+    #[test]
+    fn fixtures_match_their_lockfiles() {
+        for name in ["yarn", "pnpm", "npm"] {
+            let project = fixture(name);
+            let lockfile = Lockfile::load(&project).unwrap();
+            assert_eq!(find_drift(&project, &lockfile), vec![]);
+        }
+    }
+
+    #[test]
+    fn edited_ranges_are_drift() {
+        let react = PackageName::new("react".to_owned()).unwrap();
+        let lib = PackageKey::Name(PackageName::new("@fixture/lib".to_owned()).unwrap());
+        for name in ["yarn", "pnpm", "npm"] {
+            let mut project = fixture(name);
+            let pkg_json = &mut project.get_mut(&lib).unwrap().pkg_json;
+            pkg_json
+                .dependencies
+                .insert(react.clone(), "^18.0.0".to_owned());
+            pkg_json
+                .peer_dependencies
+                .insert(react.clone(), "^16.0.0".to_owned());
+            let lockfile = Lockfile::load(&project).unwrap();
+            let drift = find_drift(&project, &lockfile);
+            let mismatches: Vec<_> = drift.iter().map(|drift| &drift.mismatch).collect();
+            // yarn resolves by exact range so an edited range isn't in the lockfile at all
+            let expected = match name {
+                "yarn" => vec![
+                    Mismatch::Missing,
+                    Mismatch::Unsatisfied("17.0.2".to_owned()),
+                ],
+                _ => vec![
+                    Mismatch::Unsatisfied("17.0.1".to_owned()),
+                    Mismatch::Unsatisfied("17.0.1".to_owned()),
+                ],
+            };
+            assert_eq!(mismatches, expected.iter().collect::<Vec<_>>());
+        }
+    }
+}
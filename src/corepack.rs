@@ -0,0 +1,126 @@
+use crate::PackageManager;
+use anyhow::Context;
+use semver::Version;
+use serde::{Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+/// The `packageManager` field of package.json that Corepack uses to pin the
+/// package manager of a project, e.g. `pnpm@8.15.4+sha512.<hash>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinnedManager {
+    pub manager: PackageManager,
+    pub version: Version,
+    /// Corepack's checksum of the release, kept so writing the field back doesn't drop it
+    pub hash: Option<String>,
+}
+
+impl FromStr for PinnedManager {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (manager, version) = s
+            .split_once('@')
+            .with_context(|| format!("\"{}\" is missing a version", s))?;
+        // the hash after the + is Corepack's business
+        let (version, hash) = match version.split_once('+') {
+            Some((version, hash)) => (version, Some(hash.to_owned())),
+            None => (version, None),
+        };
+        Ok(PinnedManager {
+            manager: manager.parse()?,
+            version: Version::parse(version)
+                .with_context(|| format!("\"{}\" is not a valid version", version))?,
+            hash,
+        })
+    }
+}
+
+impl fmt::Display for PinnedManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{}", self.manager, self.version)
+    }
+}
+
+/// As it's written in package.json, with the hash
+impl Serialize for PinnedManager {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.hash {
+            Some(hash) => serializer.collect_str(&format_args!("{}+{}", self, hash)),
+            None => serializer.collect_str(self),
+        }
+    }
+}
+
+/**
+Compares the pinned version to the installed one. A different major version is
+an error since it would likely rewrite the lockfile in another format, any
+other difference is only worth a warning.
+*/
+fn compare_versions(pinned: &PinnedManager, installed: &Version) -> anyhow::Result<Option<String>> {
+    if installed == &pinned.version {
+        Ok(None)
+    } else if installed.major != pinned.version.major {
+        anyhow::bail!(
+            "package.json pins {} but {} {} is installed, run `corepack enable` or install {}",
+            pinned,
+            pinned.manager,
+            installed,
+            pinned
+        )
+    } else {
+        Ok(Some(format!(
+            "⚠️  package.json pins {} but {} {} is installed",
+            pinned, pinned.manager, installed
+        )))
+    }
+}
+
+/// Checks that the installed version of the package manager is the pinned one
+pub fn check_installed_version(pinned: &PinnedManager) -> anyhow::Result<()> {
+    let output = pinned
+        .manager
+        .cmd()
+        .arg("--version")
+        .output()
+        .with_context(|| format!("Failed to run {} --version", pinned.manager))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let installed = Version::parse(stdout.trim()).with_context(|| {
+        format!(
+            "Failed to parse the version of {} from {:?}",
+            pinned.manager,
+            stdout.trim()
+        )
+    })?;
+    if let Some(warning) = compare_versions(pinned, &installed)? {
+        eprintln!("{}", warning);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_package_manager_field() {
+        let pinned: PinnedManager = "pnpm@8.15.4+sha512.0bd3a9be9eb0e9a692676deec00a824ba218ba279af4df1a5a1e8bd1a8f9a6bb2a3f7b6e3c5b5e3f0fbd7d8be4fb45d96e3c0b3d0cd0b2fa3b7a6e4c0e5a1f2d0"
+            .parse()
+            .unwrap();
+        assert_eq!(pinned.manager, PackageManager::PNPM);
+        assert_eq!(pinned.version, Version::new(8, 15, 4));
+        assert!("yarn".parse::<PinnedManager>().is_err());
+        assert!("bun@1.0.0".parse::<PinnedManager>().is_err());
+    }
+
+    #[test]
+    fn compare_installed_versions() {
+        let pinned: PinnedManager = "yarn@3.6.1".parse().unwrap();
+        assert_eq!(
+            compare_versions(&pinned, &Version::new(3, 6, 1)).unwrap(),
+            None
+        );
+        assert!(compare_versions(&pinned, &Version::new(3, 2, 0))
+            .unwrap()
+            .is_some());
+        assert!(compare_versions(&pinned, &Version::new(1, 22, 19)).is_err());
+    }
+}
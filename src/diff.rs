@@ -0,0 +1,125 @@
+use console::Style;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Diffs the lines with their longest common subsequence, which is plenty for files the size of a package.json
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // lengths[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+const CONTEXT: usize = 2;
+
+/**
+A unified diff of the changed lines with a couple of lines of context around
+them, colored when the output is a terminal. Empty when nothing changed.
+*/
+pub fn unified(old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    // group the changes that are close enough for their context to overlap
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for idx in changed {
+        let start = idx.saturating_sub(CONTEXT);
+        let end = (idx + CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => hunk.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    for (start, end) in hunks {
+        let count = |lines: &[Line], old: bool| {
+            lines
+                .iter()
+                .filter(|line| match line {
+                    Line::Same(_) => true,
+                    Line::Removed(_) => old,
+                    Line::Added(_) => !old,
+                })
+                .count()
+        };
+        let old_start = count(&lines[..start], true) + 1;
+        let new_start = count(&lines[..start], false) + 1;
+        out.push_str(&format!(
+            "{}\n",
+            Style::new().cyan().apply_to(format!(
+                "@@ -{},{} +{},{} @@",
+                old_start,
+                count(&lines[start..end], true),
+                new_start,
+                count(&lines[start..end], false)
+            ))
+        ));
+        for line in &lines[start..end] {
+            match line {
+                Line::Same(line) => out.push_str(&format!(" {}\n", line)),
+                Line::Removed(line) => out.push_str(&format!(
+                    "{}\n",
+                    Style::new().red().apply_to(format!("-{}", line))
+                )),
+                Line::Added(line) => out.push_str(&format!(
+                    "{}\n",
+                    Style::new().green().apply_to(format!("+{}", line))
+                )),
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_lines_with_context() {
+        let old = "{\n  \"name\": \"a\",\n  \"version\": \"1.0.0\",\n  \"private\": true,\n  \"main\": \"index.js\",\n  \"license\": \"MIT\"\n}\n";
+        let new = old.replace("1.0.0", "1.1.0");
+        // the lines are colored when the tests run in a terminal
+        assert_eq!(
+            console::strip_ansi_codes(&unified(old, &new)),
+            "@@ -1,5 +1,5 @@\n {\n   \"name\": \"a\",\n-  \"version\": \"1.0.0\",\n+  \"version\": \"1.1.0\",\n   \"private\": true,\n   \"main\": \"index.js\",\n"
+        );
+        assert_eq!(unified(old, old), "");
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{
+    package_json::{Format, PackageJson},
+    project::{
+        find_packages, index_packages, load_package, parse_packages, BrokenManifest, Package,
+        PackageKey,
+    },
+    PackageManager,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Bumped whenever the format changes so caches written by older versions are thrown away
+const CACHE_VERSION: u32 = 3;
+
+type Modified = Option<SystemTime>;
+
+#[derive(Serialize, Deserialize)]
+struct CachedPackage {
+    pkg_json_path: PathBuf,
+    modified: SystemTime,
+    pkg_json: PackageJson,
+    format: Format,
+}
+
+/**
+The workspace packages found the last time, along with the modification times
+of everything that decides which packages there are. A new package changes the
+modification time of the directory it's added to, which is one of the
+directories that were searched or one where a glob starts from.
+*/
+#[derive(Serialize, Deserialize)]
+struct DiscoveryCache {
+    version: u32,
+    manager: String,
+    globs: Vec<String>,
+    /// The root package.json and pnpm-workspace.yaml
+    config: Vec<(PathBuf, Modified)>,
+    /// The directories that were searched and the directories the globs start from
+    dirs: Vec<(PathBuf, Modified)>,
+    packages: Vec<CachedPackage>,
+}
+
+fn modified(path: &Path) -> Modified {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn cache_path(root: &Path) -> PathBuf {
+    root.join("node_modules/.cache/pyn/workspaces.json")
+}
+
+fn config_files(root: &Path) -> Vec<(PathBuf, Modified)> {
+    ["package.json", "pnpm-workspace.yaml"]
+        .into_iter()
+        .map(|file| {
+            let path = root.join(file);
+            let modified = modified(&path);
+            (path, modified)
+        })
+        .collect()
+}
+
+fn watched_dirs(dirs: Vec<PathBuf>) -> Vec<(PathBuf, Modified)> {
+    dirs.into_iter()
+        .map(|dir| {
+            let modified = modified(&dir);
+            (dir, modified)
+        })
+        .collect()
+}
+
+impl DiscoveryCache {
+    fn read(root: &Path, manager: PackageManager, globs: &[String]) -> Option<DiscoveryCache> {
+        let contents = fs::read(cache_path(root)).ok()?;
+        let cache: DiscoveryCache = serde_json::from_slice(&contents).ok()?;
+        let fresh = cache.version == CACHE_VERSION
+            && cache.manager == manager.to_string()
+            && cache.globs == globs
+            && cache.config == config_files(root)
+            && cache
+                .dirs
+                .iter()
+                .all(|(dir, cached)| modified(dir) == *cached);
+        fresh.then_some(cache)
+    }
+
+    /// Best effort since the cache is only there to make things faster
+    fn write(&self, root: &Path) {
+        // wait until there's a node_modules rather than creating one in a fresh clone
+        if !root.join("node_modules").is_dir() {
+            return;
+        }
+        let path = cache_path(root);
+        if let Ok(contents) = serde_json::to_vec(self) {
+            fs::create_dir_all(path.parent().unwrap()).ok();
+            fs::write(path, contents).ok();
+        }
+    }
+
+    /**
+    The cached packages, re-reading the ones with a package.json that changed,
+    and whether any of them did. `None` when one of them was removed.
+    */
+    fn packages(&mut self, root: &Path) -> Option<(Vec<Package>, Vec<BrokenManifest>, bool)> {
+        let mut changed = false;
+        let mut packages = vec![];
+        let mut broken = vec![];
+        for cached in &mut self.packages {
+            match modified(&cached.pkg_json_path)? {
+                modified if modified == cached.modified => {
+                    let mut pkg_json = cached.pkg_json.clone();
+                    pkg_json.format = cached.format.clone();
+                    packages.push(Package::new(root, cached.pkg_json_path.clone(), pkg_json))
+                }
+                // a package that's broken now keeps its old entry so it's read again next time
+                modified => match load_package(root, &cached.pkg_json_path) {
+                    Ok(pkg) => {
+                        cached.modified = modified;
+                        cached.pkg_json = pkg.pkg_json.clone();
+                        cached.format = pkg.pkg_json.format.clone();
+                        packages.push(pkg);
+                        changed = true;
+                    }
+                    Err(manifest) => broken.push(manifest),
+                },
+            }
+        }
+        Some((packages, broken, changed))
+    }
+}
+
+/**
+Finds and loads the workspace packages, reusing what was found last time when
+none of the files and directories that decide it changed. The cache lives in
+`node_modules/.cache/pyn`, deleting it makes pyn look for the packages again.
+Packages with a broken package.json are returned separately.
+*/
+pub fn discover_packages(
+    root: &Package,
+    manager: PackageManager,
+    globs: &[String],
+) -> anyhow::Result<(HashMap<PackageKey, Package>, Vec<BrokenManifest>)> {
+    let dir = root.path();
+    if let Some(mut cache) = DiscoveryCache::read(dir, manager, globs) {
+        if let Some((packages, broken, changed)) = cache.packages(dir) {
+            if changed {
+                cache.write(dir);
+            }
+            return Ok((index_packages(root, packages)?, broken));
+        }
+    }
+
+    let found = find_packages(dir, manager, globs)?;
+    // checked before reading so a change while reading invalidates the cache next time
+    let modified: Vec<Modified> = found
+        .pkg_json_paths
+        .iter()
+        .map(|path| modified(path))
+        .collect();
+    let dirs = watched_dirs(found.dirs);
+    let (packages, mut broken) = parse_packages(dir, &found.pkg_json_paths);
+    broken.extend(found.broken);
+    // the broken packages aren't cached so they're found and reported every time
+    let cached_packages: Option<Vec<CachedPackage>> = packages
+        .iter()
+        .zip(modified)
+        .map(|(pkg, modified)| {
+            Some(CachedPackage {
+                pkg_json_path: pkg.pkg_json_path.clone(),
+                modified: modified?,
+                pkg_json: pkg.pkg_json.clone(),
+                format: pkg.pkg_json.format.clone(),
+            })
+        })
+        .collect();
+    if let Some(cached_packages) = cached_packages.filter(|_| broken.is_empty()) {
+        DiscoveryCache {
+            version: CACHE_VERSION,
+            manager: manager.to_string(),
+            globs: globs.to_vec(),
+            config: config_files(dir),
+            dirs,
+            packages: cached_packages,
+        }
+        .write(dir);
+    }
+    Ok((index_packages(root, packages)?, broken))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::time::Duration;
+
+    #[test]
+    fn cached_discovery() {
+        let dir = TestDir::new();
+        let root = dir.path();
+        let write = |path: &str, contents: &str| {
+            let path = dir.write(path, contents);
+            // make sure the change is visible even on file systems with coarse timestamps
+            let later = SystemTime::now() + Duration::from_secs(10);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(later)
+                .unwrap();
+        };
+        write(
+            "package.json",
+            r#"{ "name": "root", "workspaces": ["packages/*"] }"#,
+        );
+        write("yarn.lock", "");
+        write("packages/a/package.json", r#"{ "name": "a" }"#);
+        write("packages/b/package.json", r#"{ "name": "b" }"#);
+        fs::create_dir_all(root.join("node_modules")).unwrap();
+        let root_pkg = load_package(root, &root.join("package.json")).unwrap();
+        let globs = vec!["packages/*".to_owned()];
+        let discover = || {
+            let mut names: Vec<String> = discover_packages(&root_pkg, PackageManager::Yarn, &globs)
+                .unwrap()
+                .0
+                .into_keys()
+                .map(|name| name.to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let first = discover();
+        // the cached manifests are used rather than the files when nothing changed
+        let cache_file = cache_path(root);
+        let cached = fs::read_to_string(&cache_file).unwrap();
+        fs::write(
+            &cache_file,
+            cached.replace(r#""name":"b""#, r#""name":"cached-b""#),
+        )
+        .unwrap();
+        let from_cache = discover();
+        write("packages/a/package.json", r#"{ "name": "renamed-a" }"#);
+        let edited = discover();
+        fs::create_dir_all(root.join("packages/c")).unwrap();
+        fs::write(root.join("packages/c/package.json"), r#"{ "name": "c" }"#).unwrap();
+        // adding c may not change the directory's timestamp within the same tick
+        fs::File::open(root.join("packages"))
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(20))
+            .unwrap();
+        let added = discover();
+
+        assert_eq!(first, vec!["a", "b"]);
+        assert_eq!(from_cache, vec!["a", "cached-b"]);
+        assert_eq!(edited, vec!["cached-b", "renamed-a"]);
+        assert_eq!(added, vec!["b", "c", "renamed-a"]);
+    }
+
+    #[test]
+    fn package_added_to_existing_dir() {
+        let dir = TestDir::new();
+        let root = dir.path();
+        dir.write(
+            "package.json",
+            r#"{ "name": "root", "workspaces": ["packages/*"] }"#,
+        );
+        dir.write("yarn.lock", "");
+        dir.write("packages/a/package.json", r#"{ "name": "a" }"#);
+        fs::create_dir_all(root.join("packages/c/src")).unwrap();
+        fs::create_dir_all(root.join("node_modules")).unwrap();
+        let root_pkg = load_package(root, &root.join("package.json")).unwrap();
+        let globs = vec!["packages/*".to_owned()];
+        let discover = || {
+            let mut names: Vec<String> = discover_packages(&root_pkg, PackageManager::Yarn, &globs)
+                .unwrap()
+                .0
+                .into_keys()
+                .map(|name| name.to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(discover(), vec!["a"]);
+        dir.write("packages/c/package.json", r#"{ "name": "c" }"#);
+        // make sure the change is visible even on file systems with coarse timestamps
+        fs::File::open(root.join("packages/c"))
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(discover(), vec!["a", "c"]);
+    }
+}
@@ -0,0 +1,45 @@
+use crate::project::Project;
+
+/**
+Lists the workspace packages that were left out because their package.json is
+broken, and the fields of the others that have a shape pyn can't read.
+*/
+pub fn doctor(project: &Project) -> anyhow::Result<()> {
+    let mut invalid: Vec<_> = project
+        .iter()
+        .flat_map(|pkg| {
+            pkg.pkg_json
+                .invalid_fields()
+                .into_iter()
+                .map(move |err| (&pkg.pkg_json_path, err))
+        })
+        .collect();
+    invalid.sort_by_key(|(path, _)| *path);
+    if project.broken.is_empty() && invalid.is_empty() {
+        println!(
+            "✅ Every package.json in the project is valid ({} packages)",
+            project.iter().count()
+        );
+        return Ok(());
+    }
+    if !project.broken.is_empty() {
+        println!(
+            "❌ {} workspace packages are left out because their package.json is broken:",
+            project.broken.len()
+        );
+        for manifest in &project.broken {
+            println!("  {}", manifest);
+        }
+    }
+    if !invalid.is_empty() {
+        println!("❌ {} package.json fields can't be read:", invalid.len());
+        for (path, err) in &invalid {
+            println!("  {}: {:#}", path.display(), err);
+        }
+    }
+    anyhow::bail!(
+        "Found {} broken package.json files and {} invalid fields",
+        project.broken.len(),
+        invalid.len()
+    )
+}
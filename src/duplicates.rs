@@ -0,0 +1,205 @@
+use crate::{
+    lockfile::Lockfile,
+    project::{PackageKey, Project},
+    range::{self, Range},
+    PackageName,
+};
+use semver::Version;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A version of a package in the lockfile
+#[derive(Debug, PartialEq)]
+pub struct ResolvedCopy {
+    pub version: String,
+    /// The ranges that resolved to this copy
+    pub ranges: Vec<String>,
+    /// The workspace packages that end up installing this copy
+    pub workspaces: BTreeSet<PackageKey>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Dedupe {
+    /// One of the locked versions already satisfies every range
+    Locked(String),
+    /// The ranges intersect but none of the locked versions are in the intersection
+    Possible,
+    Impossible,
+    /// The range isn't a semver range so there's no telling
+    Unknown(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Duplicate {
+    pub name: PackageName,
+    pub copies: Vec<ResolvedCopy>,
+    pub dedupe: Dedupe,
+}
+
+fn dedupe(copies: &[ResolvedCopy]) -> Dedupe {
+    let mut ranges = vec![];
+    for raw in copies.iter().flat_map(|copy| &copy.ranges) {
+        match raw.parse::<Range>() {
+            Ok(range) => ranges.push(range),
+            Err(_) => return Dedupe::Unknown(raw.clone()),
+        }
+    }
+    let satisfying = copies.iter().rev().find(|copy| {
+        Version::parse(&copy.version)
+            .map(|version| ranges.iter().all(|range| range.satisfies(&version)))
+            .unwrap_or(false)
+    });
+    match satisfying {
+        Some(copy) => Dedupe::Locked(copy.version.clone()),
+        None if range::intersects(&ranges) => Dedupe::Possible,
+        None => Dedupe::Impossible,
+    }
+}
+
+/// Finds every package that's resolved to more than one version in the lockfile.
+pub fn find_duplicates(project: &Project, lockfile: &Lockfile) -> Vec<Duplicate> {
+    let mut by_name: BTreeMap<&PackageName, BTreeMap<&str, ResolvedCopy>> = BTreeMap::new();
+    for pkg in &lockfile.packages {
+        // pnpm has an entry per set of resolved peer dependencies so merge by version
+        let copy = by_name
+            .entry(&pkg.name)
+            .or_default()
+            .entry(&pkg.version)
+            .or_insert_with(|| ResolvedCopy {
+                version: pkg.version.clone(),
+                ranges: vec![],
+                workspaces: BTreeSet::new(),
+            });
+        for range in &pkg.ranges {
+            if !copy.ranges.contains(range) {
+                copy.ranges.push(range.clone());
+            }
+        }
+    }
+    for pkg in project.iter() {
+        let importer = match lockfile.importer(project, pkg) {
+            Some(importer) => importer,
+            None => continue,
+        };
+        for idx in lockfile.reachable(importer) {
+            let locked = &lockfile.packages[idx];
+            if let Some(copy) = by_name
+                .get_mut(&locked.name)
+                .and_then(|copies| copies.get_mut(locked.version.as_str()))
+            {
+                copy.workspaces.insert(pkg.key.clone());
+            }
+        }
+    }
+    by_name
+        .into_iter()
+        .filter(|(_, copies)| copies.len() > 1)
+        .map(|(name, copies)| {
+            let mut copies: Vec<ResolvedCopy> = copies.into_values().collect();
+            copies.sort_by(
+                |a, b| match (Version::parse(&a.version), Version::parse(&b.version)) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    _ => a.version.cmp(&b.version),
+                },
+            );
+            Duplicate {
+                name: name.clone(),
+                dedupe: dedupe(&copies),
+                copies,
+            }
+        })
+        .collect()
+}
+
+pub fn duplicates(project: &Project) -> anyhow::Result<()> {
+    let lockfile = Lockfile::load(project)?;
+    let duplicates = find_duplicates(project, &lockfile);
+    if duplicates.is_empty() {
+        println!("Every package is only resolved to a single version");
+    }
+    for duplicate in duplicates {
+        println!(
+            "{} is resolved to {} versions",
+            duplicate.name,
+            duplicate.copies.len()
+        );
+        for copy in &duplicate.copies {
+            let workspaces: Vec<_> = copy.workspaces.iter().map(|key| key.to_string()).collect();
+            println!(
+                "  {} ({}) used by {}",
+                copy.version,
+                copy.ranges.join(", "),
+                workspaces.join(", ")
+            );
+        }
+        match duplicate.dedupe {
+            Dedupe::Locked(version) => {
+                println!(
+                    "  {} satisfies every range so it could be the only copy",
+                    version
+                )
+            }
+            Dedupe::Possible => println!("  A single version could satisfy every range"),
+            Dedupe::Impossible => println!("  No single version can satisfy every range"),
+            Dedupe::Unknown(range) => println!(
+                "  Can't tell whether a single version could be used since {} isn't a semver range",
+                range
+            ),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn duplicate_react() {
+        for fixture in ["yarn", "pnpm", "npm"] {
+            let mut dir = env::current_dir().unwrap();
+            dir.push("fixtures");
+            dir.push(fixture);
+            let project = Project::find(&dir, None).unwrap();
+            let lockfile = Lockfile::load(&project).unwrap();
+            let duplicates = find_duplicates(&project, &lockfile);
+            assert_eq!(duplicates.len(), 1);
+            let react = &duplicates[0];
+            assert_eq!(react.name.as_str(), "react");
+            let versions: Vec<_> = react
+                .copies
+                .iter()
+                .map(|copy| copy.version.as_str())
+                .collect();
+            assert_eq!(versions, vec!["17.0.1", "17.0.2"]);
+            let lib = PackageKey::Name(PackageName::new("@fixture/lib".to_owned()).unwrap());
+            assert_eq!(react.copies[0].workspaces, BTreeSet::from([lib]));
+            assert_eq!(react.dedupe, Dedupe::Locked("17.0.2".to_owned()));
+        }
+    }
+
+    #[test]
+    fn dedupe_by_intersection() {
+        let copy = |version: &str, range: &str| ResolvedCopy {
+            version: version.to_owned(),
+            ranges: vec![range.to_owned()],
+            workspaces: BTreeSet::new(),
+        };
+        assert_eq!(
+            dedupe(&[copy("1.0.0", "~1.0.0"), copy("1.2.0", "^1.1.0")]),
+            Dedupe::Impossible
+        );
+        assert_eq!(
+            dedupe(&[copy("1.1.0", "^1.1.0"), copy("1.2.0", ">=1.2.0")]),
+            Dedupe::Locked("1.2.0".to_owned())
+        );
+        assert_eq!(
+            dedupe(&[copy("1.0.5", ">=1.0.0 <1.2.0"), copy("1.3.0", ">=1.1.0")]),
+            Dedupe::Possible
+        );
+        assert_eq!(
+            dedupe(&[copy("1.1.0", "^1.1.0"), copy("2.0.0", "github:a/b")]),
+            Dedupe::Unknown("github:a/b".to_owned())
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use crate::{
+    graph::WorkspaceGraph,
+    project::{Package, PackageKey, Project},
+};
+use anyhow::Context;
+use globset::{Glob, GlobMatcher};
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+use structopt::StructOpt;
+
+#[derive(Debug, Clone)]
+enum Selector {
+    Name(GlobMatcher),
+    /// The packages in the directory or anywhere inside it, relative to the current directory
+    Path(PathBuf),
+}
+
+/// Selects workspace packages by name glob (`@scope/*`) or by directory
+/// (`./packages/a`). `foo...` also selects the packages foo depends on, `...foo`
+/// the packages that depend on foo and `!foo` excludes the packages instead.
+/// Packages without a name can only be selected by directory.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    raw: String,
+    exclude: bool,
+    selector: Selector,
+    dependencies: bool,
+    dependents: bool,
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (exclude, rest) = match s.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (dependents, rest) = match rest.strip_prefix("...") {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let (dependencies, rest) = match rest.strip_suffix("...") {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        if rest.is_empty() {
+            anyhow::bail!("\"{}\" doesn't select any packages", s);
+        }
+        let selector = if rest.starts_with('.') || rest.starts_with('/') {
+            Selector::Path(PathBuf::from(rest))
+        } else {
+            Selector::Name(
+                Glob::new(rest)
+                    .with_context(|| format!("Invalid package name glob \"{}\"", rest))?
+                    .compile_matcher(),
+            )
+        };
+        Ok(Filter {
+            raw: s.to_owned(),
+            exclude,
+            selector,
+            dependencies,
+            dependents,
+        })
+    }
+}
+
+/// Resolves the `.` and `..` in a path without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+impl Filter {
+    fn matches(&self, pkg: &Package, current_dir: &Path) -> bool {
+        match &self.selector {
+            Selector::Name(glob) => pkg
+                .pkg_json
+                .name
+                .as_ref()
+                .is_some_and(|name| glob.is_match(name.as_str())),
+            Selector::Path(path) => {
+                normalize(pkg.path()).starts_with(normalize(&current_dir.join(path)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct FilterOpts {
+    /// Only targets the matching workspace packages: a name glob (@scope/*), a
+    /// directory (./packages/a), foo... to include its dependencies, ...foo to
+    /// include its dependents or !foo to exclude packages
+    #[structopt(long = "filter", short = "F", number_of_values = 1)]
+    filters: Vec<Filter>,
+    /// Only targets the packages with files that changed since the git ref, and
+    /// the packages that depend on them
+    #[structopt(long)]
+    since: Option<String>,
+}
+
+fn git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("Failed to run git {}", args.join(" ")))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/**
+The packages with files that changed since the point the current branch forked
+from the git ref, including uncommitted and untracked files, and the packages
+that depend on them. Changes outside of the workspace packages, like to the
+lockfile, don't select the root package of a project with workspaces.
+*/
+fn changed_since(
+    project: &Project,
+    graph: &WorkspaceGraph,
+    git_ref: &str,
+) -> anyhow::Result<BTreeSet<PackageKey>> {
+    let merge_base = git(project.dir(), &["merge-base", git_ref, "HEAD"])?;
+    let mut changed_files = git(
+        project.dir(),
+        &["diff", "--name-only", "--relative", merge_base.trim()],
+    )?;
+    // new files that haven't been added yet aren't in the diff
+    changed_files.push_str(&git(
+        project.dir(),
+        &["ls-files", "--others", "--exclude-standard"],
+    )?);
+    let mut changed = BTreeSet::new();
+    for file in changed_files.lines() {
+        let pkg = match project.closest_pkg(&project.dir().join(file)) {
+            Some(pkg) => pkg,
+            None => continue,
+        };
+        if project.packages.is_some() && pkg.path() == project.dir() {
+            continue;
+        }
+        if changed.insert(pkg.key.clone()) {
+            changed.extend(
+                graph
+                    .transitive_dependents(&pkg.key)
+                    .into_iter()
+                    .map(|pkg| pkg.key.clone()),
+            );
+        }
+    }
+    Ok(changed)
+}
+
+impl FilterOpts {
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty() && self.since.is_none()
+    }
+
+    /**
+    The keys of the packages the filters select, or `None` when there are no
+    filters. Filters that only exclude packages start from every package and
+    `--since` narrows down the packages the other filters select.
+    */
+    pub fn select(
+        &self,
+        project: &Project,
+        current_dir: &Path,
+    ) -> anyhow::Result<Option<BTreeSet<PackageKey>>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let graph = WorkspaceGraph::new(project);
+        let mut selected = BTreeSet::new();
+        let mut excluded = BTreeSet::new();
+        for filter in &self.filters {
+            let matched: Vec<&Package> = project
+                .iter()
+                .filter(|pkg| filter.matches(pkg, current_dir))
+                .collect();
+            if matched.is_empty() {
+                anyhow::bail!("No packages match the filter {}", filter.raw);
+            }
+            let keys = if filter.exclude {
+                &mut excluded
+            } else {
+                &mut selected
+            };
+            for pkg in matched {
+                if filter.dependencies {
+                    keys.extend(
+                        graph
+                            .transitive_dependencies(&pkg.key)
+                            .into_iter()
+                            .map(|pkg| pkg.key.clone()),
+                    );
+                }
+                if filter.dependents {
+                    keys.extend(
+                        graph
+                            .transitive_dependents(&pkg.key)
+                            .into_iter()
+                            .map(|pkg| pkg.key.clone()),
+                    );
+                }
+                keys.insert(pkg.key.clone());
+            }
+        }
+        if self.filters.iter().all(|filter| filter.exclude) {
+            selected = project.iter().map(|pkg| pkg.key.clone()).collect();
+        }
+        if let Some(git_ref) = &self.since {
+            selected = &selected & &changed_since(project, &graph, git_ref)?;
+        }
+        Ok(Some(&selected - &excluded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::env;
+
+    fn select(filters: &[&str], current_dir: &str) -> anyhow::Result<Vec<String>> {
+        let mut dir = env::current_dir().unwrap();
+        dir.push("fixtures/pnpm");
+        let project = Project::find(&dir, None).unwrap();
+        let opts = FilterOpts {
+            filters: filters
+                .iter()
+                .map(|filter| filter.parse())
+                .collect::<anyhow::Result<_>>()?,
+            since: None,
+        };
+        let selected = opts.select(&project, &dir.join(current_dir))?.unwrap();
+        Ok(selected.into_iter().map(|name| name.to_string()).collect())
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(
+            select(&["@fixture/*"], "").unwrap(),
+            vec!["@fixture/app", "@fixture/lib"]
+        );
+        assert_eq!(
+            select(&["./packages/lib"], "").unwrap(),
+            vec!["@fixture/lib"]
+        );
+        assert_eq!(
+            select(&["../lib"], "packages/app").unwrap(),
+            vec!["@fixture/lib"]
+        );
+        assert_eq!(
+            select(&["@fixture/app..."], "").unwrap(),
+            vec!["@fixture/app", "@fixture/lib"]
+        );
+        assert_eq!(
+            select(&["...@fixture/lib"], "").unwrap(),
+            vec!["@fixture/app", "@fixture/lib"]
+        );
+        assert_eq!(
+            select(&["@fixture/app...", "!@fixture/lib"], "").unwrap(),
+            vec!["@fixture/app"]
+        );
+        assert_eq!(select(&["!@fixture/*"], "").unwrap(), vec!["fixture"]);
+        assert_eq!(
+            select(&["@other/*"], "").unwrap_err().to_string(),
+            "No packages match the filter @other/*"
+        );
+    }
+
+    #[test]
+    fn since() {
+        let root = TestDir::new();
+        root.write(
+            "package.json",
+            r#"{ "name": "root", "workspaces": ["packages/*"] }"#,
+        );
+        root.write("yarn.lock", "");
+        root.write("packages/a/package.json", r#"{ "name": "a" }"#);
+        root.write(
+            "packages/b/package.json",
+            r#"{ "name": "b", "devDependencies": { "a": "*" } }"#,
+        );
+        root.write("packages/c/package.json", r#"{ "name": "c" }"#);
+        let run_git = |args: &[&str]| {
+            let mut all_args = vec!["-c", "user.name=pyn", "-c", "user.email=pyn@example.com"];
+            all_args.extend(args);
+            git(root.path(), &all_args).unwrap();
+        };
+        run_git(&["init", "-q"]);
+        run_git(&["add", "."]);
+        run_git(&["commit", "-q", "-m", "initial"]);
+        run_git(&["branch", "base"]);
+        root.write("packages/a/index.js", "");
+        root.write("yarn.lock", "# changed");
+        run_git(&["add", "."]);
+        run_git(&["commit", "-q", "-m", "change a"]);
+        root.write("packages/c/index.js", "");
+
+        let project = Project::find(root.path(), None).unwrap();
+        let select = |filters: Vec<Filter>| {
+            FilterOpts {
+                filters,
+                since: Some("base".to_owned()),
+            }
+            .select(&project, root.path())
+            .unwrap()
+        };
+        let names = |selected: Option<BTreeSet<PackageKey>>| -> Vec<String> {
+            selected
+                .unwrap()
+                .into_iter()
+                .map(|name| name.to_string())
+                .collect()
+        };
+        assert_eq!(names(select(vec![])), vec!["a", "b", "c"]);
+        assert_eq!(names(select(vec!["!c".parse().unwrap()])), vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{
+    project::{Package, PackageKey, Project},
+    PackageName,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+/**
+How the packages in a workspace depend on each other, through any kind of
+dependency (including dev, optional and peer dependencies) that names another
+workspace package. The root package is part of the graph too.
+*/
+#[derive(Debug)]
+pub struct WorkspaceGraph<'a> {
+    /// Sorted by key so everything derived from the graph is deterministic
+    packages: Vec<&'a Package>,
+    indices: HashMap<&'a PackageKey, usize>,
+    dependencies: Vec<BTreeSet<usize>>,
+    dependents: Vec<BTreeSet<usize>>,
+}
+
+/// A cycle of workspace packages depending on each other, starting and ending with the same package
+#[derive(Debug, PartialEq)]
+pub struct Cycle(pub Vec<PackageKey>);
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = self.0.iter().map(|key| key.to_string()).collect();
+        write!(f, "Found a dependency cycle: {}", names.join(" -> "))
+    }
+}
+
+impl std::error::Error for Cycle {}
+
+impl<'a> WorkspaceGraph<'a> {
+    pub fn new(project: &'a Project) -> WorkspaceGraph<'a> {
+        let mut packages: Vec<&Package> = project.iter().collect();
+        packages.sort_by(|a, b| a.key.cmp(&b.key));
+        let indices: HashMap<&PackageKey, usize> = packages
+            .iter()
+            .enumerate()
+            .map(|(idx, pkg)| (&pkg.key, idx))
+            .collect();
+        // only packages with a name can be depended on
+        let by_name: HashMap<&PackageName, usize> = packages
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, pkg)| Some((pkg.pkg_json.name.as_ref()?, idx)))
+            .collect();
+        let mut dependencies = vec![BTreeSet::new(); packages.len()];
+        let mut dependents = vec![BTreeSet::new(); packages.len()];
+        for (idx, pkg) in packages.iter().enumerate() {
+            let pkg_json = &pkg.pkg_json;
+            let names = pkg_json
+                .iter_normal_deps()
+                .chain(std::iter::once(&pkg_json.peer_dependencies))
+                .flat_map(|deps| deps.keys());
+            for name in names {
+                if let Some(&dep_idx) = by_name.get(name) {
+                    if dep_idx != idx {
+                        dependencies[idx].insert(dep_idx);
+                        dependents[dep_idx].insert(idx);
+                    }
+                }
+            }
+        }
+        WorkspaceGraph {
+            packages,
+            indices,
+            dependencies,
+            dependents,
+        }
+    }
+
+    fn resolve(&self, indices: impl IntoIterator<Item = usize>) -> Vec<&'a Package> {
+        indices.into_iter().map(|idx| self.packages[idx]).collect()
+    }
+
+    /// Everything reachable from the package through the edges, not including the package itself
+    fn reachable(&self, key: &PackageKey, edges: &[BTreeSet<usize>]) -> BTreeSet<usize> {
+        let start = match self.indices.get(key) {
+            Some(&idx) => idx,
+            None => return BTreeSet::new(),
+        };
+        let mut found = BTreeSet::new();
+        let mut stack = vec![start];
+        while let Some(idx) = stack.pop() {
+            for &next in &edges[idx] {
+                if next != start && found.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        found
+    }
+
+    /// The workspace packages the package depends on directly
+    pub fn dependencies(&self, key: &PackageKey) -> Vec<&'a Package> {
+        match self.indices.get(key) {
+            Some(&idx) => self.resolve(self.dependencies[idx].iter().copied()),
+            None => vec![],
+        }
+    }
+
+    /// The workspace packages that depend on the package directly
+    pub fn dependents(&self, key: &PackageKey) -> Vec<&'a Package> {
+        match self.indices.get(key) {
+            Some(&idx) => self.resolve(self.dependents[idx].iter().copied()),
+            None => vec![],
+        }
+    }
+
+    pub fn transitive_dependencies(&self, key: &PackageKey) -> Vec<&'a Package> {
+        self.resolve(self.reachable(key, &self.dependencies))
+    }
+
+    pub fn transitive_dependents(&self, key: &PackageKey) -> Vec<&'a Package> {
+        self.resolve(self.reachable(key, &self.dependents))
+    }
+
+    /**
+    Orders the packages so every package comes after the workspace packages it
+    depends on, packages that don't depend on each other are ordered by key.
+    */
+    pub fn topological_order(&self) -> Result<Vec<&'a Package>, Cycle> {
+        let mut remaining: Vec<usize> = self.dependencies.iter().map(|deps| deps.len()).collect();
+        let mut ready: BTreeSet<usize> = (0..self.packages.len())
+            .filter(|&idx| remaining[idx] == 0)
+            .collect();
+        let mut order = vec![];
+        while let Some(idx) = ready.pop_first() {
+            order.push(idx);
+            for &dependent in &self.dependents[idx] {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+        if order.len() == self.packages.len() {
+            return Ok(self.resolve(order));
+        }
+        // every package left over depends on another one that's left over so
+        // following those dependencies has to end up going around a cycle
+        let mut path = vec![(0..self.packages.len())
+            .find(|&idx| remaining[idx] != 0)
+            .unwrap()];
+        loop {
+            let last = *path.last().unwrap();
+            let next = *self.dependencies[last]
+                .iter()
+                .find(|&&dep| remaining[dep] != 0)
+                .unwrap();
+            if let Some(start) = path.iter().position(|&idx| idx == next) {
+                let mut cycle: Vec<PackageKey> = path[start..]
+                    .iter()
+                    .map(|&idx| self.packages[idx].key.clone())
+                    .collect();
+                cycle.push(self.packages[next].key.clone());
+                return Err(Cycle(cycle));
+            }
+            path.push(next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn names(packages: Vec<&Package>) -> Vec<String> {
+        packages
+            .into_iter()
+            .map(|pkg| pkg.key.to_string())
+            .collect()
+    }
+
+    fn key(name: &str) -> PackageKey {
+        PackageKey::Name(PackageName::new(name.to_owned()).unwrap())
+    }
+
+    fn fixture(name: &str) -> Project {
+        let mut dir = env::current_dir().unwrap();
+        dir.push("fixtures");
+        dir.push(name);
+        Project::find(&dir, None).unwrap()
+    }
+
+    #[test]
+    fn fixture_graph() {
+        let project = fixture("yarn");
+        let graph = WorkspaceGraph::new(&project);
+        assert_eq!(
+            names(graph.dependencies(&key("@fixture/app"))),
+            vec!["@fixture/lib"]
+        );
+        assert_eq!(
+            names(graph.dependents(&key("@fixture/lib"))),
+            vec!["@fixture/app"]
+        );
+        assert_eq!(
+            names(graph.topological_order().unwrap()),
+            vec!["@fixture/lib", "@fixture/app", "fixture"]
+        );
+    }
+
+    #[test]
+    fn cycles() {
+        let mut project = fixture("basic");
+        let dep = |project: &mut Project, from: &str, to: &str| {
+            let to = PackageName::new(to.to_owned()).unwrap();
+            let pkg_json = &mut project.get_mut(&key(from)).unwrap().pkg_json;
+            pkg_json.dev_dependencies.insert(to, "*".to_owned());
+        };
+        dep(&mut project, "something-a", "somethin-b");
+        dep(&mut project, "somethin-b", "somethin-c");
+        let graph = WorkspaceGraph::new(&project);
+        assert_eq!(
+            names(graph.transitive_dependencies(&key("something-a"))),
+            vec!["somethin-b", "somethin-c"]
+        );
+        dep(&mut project, "somethin-c", "somethin-b");
+        let graph = WorkspaceGraph::new(&project);
+        assert_eq!(
+            graph.topological_order().unwrap_err().to_string(),
+            "Found a dependency cycle: somethin-b -> somethin-c -> somethin-b"
+        );
+    }
+
+    #[test]
+    fn unnamed_packages() {
+        let mut project = fixture("yarn");
+        let mut app = project.get_mut(&key("@fixture/app")).unwrap().clone();
+        let app_key = PackageKey::Path("packages/app".to_owned());
+        app.pkg_json.name = None;
+        app.key = app_key.clone();
+        let packages = project.packages.as_mut().unwrap();
+        packages.remove(&key("@fixture/app"));
+        packages.insert(app_key.clone(), app);
+        let graph = WorkspaceGraph::new(&project);
+        assert_eq!(names(graph.dependencies(&app_key)), vec!["@fixture/lib"]);
+        assert_eq!(
+            names(graph.topological_order().unwrap()),
+            vec!["@fixture/lib", "fixture", "./packages/app"]
+        );
+    }
+}
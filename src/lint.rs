@@ -0,0 +1,308 @@
+use crate::{
+    project::{PackageKey, Project},
+    range::{single_version, Range},
+    PackageName,
+};
+use anyhow::Context;
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Which of the ranges a dependency is declared with the others get aligned to
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Policy {
+    /// The newest version the ranges start at, with the operator most of them use
+    Highest,
+    /// The oldest version the ranges start at, with the operator most of them use
+    Lowest,
+    /// The range used by the most packages, the highest of them on a tie
+    MostCommon,
+}
+
+impl FromStr for Policy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "highest" => Ok(Policy::Highest),
+            "lowest" => Ok(Policy::Lowest),
+            "most-common" => Ok(Policy::MostCommon),
+            _ => anyhow::bail!("\"{}\" is not one of highest, lowest or most-common", s),
+        }
+    }
+}
+
+/**
+The `pyn.lint.versions` field in the package.json at the root of the project,
+e.g. `{ "policy": "most-common", "allow": ["typescript"] }`.
+*/
+#[derive(Debug, Default, Deserialize)]
+pub struct VersionsConfig {
+    policy: Option<Policy>,
+    /// Dependencies that are allowed to be declared with different ranges
+    #[serde(default)]
+    allow: Vec<PackageName>,
+}
+
+impl VersionsConfig {
+    fn read(project: &Project) -> anyhow::Result<VersionsConfig> {
+        match project
+            .root
+            .pkg_json
+            .get("pyn")
+            .and_then(|config| config.pointer("/lint/versions"))
+        {
+            Some(config) => serde_json::from_value(config.clone()).with_context(|| {
+                format!(
+                    "Failed to read pyn.lint.versions in {}",
+                    project.root.pkg_json_path.display()
+                )
+            }),
+            None => Ok(VersionsConfig::default()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub dependency: PackageName,
+    /// Each range and the packages that declare the dependency with it
+    pub ranges: BTreeMap<String, Vec<PackageKey>>,
+    /// The range to align to, `None` when some of the ranges aren't semver ranges
+    pub fix: Option<String>,
+}
+
+fn choose_range(ranges: &BTreeMap<String, Vec<PackageKey>>, policy: Policy) -> Option<String> {
+    let mut parsed = vec![];
+    for (raw, packages) in ranges {
+        parsed.push((raw, raw.parse::<Range>().ok()?, packages.len()));
+    }
+    let chosen = match policy {
+        Policy::Highest => parsed.iter().max_by(|a, b| a.1.cmp_newest(&b.1)),
+        Policy::Lowest => parsed.iter().min_by(|a, b| a.1.cmp_newest(&b.1)),
+        Policy::MostCommon => parsed
+            .iter()
+            .max_by(|a, b| a.2.cmp(&b.2).then_with(|| a.1.cmp_newest(&b.1))),
+    };
+    let (raw, _, _) = chosen?;
+    Some(match policy {
+        Policy::MostCommon => (*raw).clone(),
+        Policy::Highest | Policy::Lowest => with_common_operator(ranges, raw),
+    })
+}
+
+/**
+Moves the chosen range to the operator used by the most packages, so aligning
+to the newest version doesn't narrow every `^` range to `~` when the newest
+range happens to be a `~` one. On a tie the wider operator wins. Ranges that
+aren't a single version with an operator are left as they are.
+*/
+fn with_common_operator(ranges: &BTreeMap<String, Vec<PackageKey>>, chosen: &str) -> String {
+    let width = |operator: &str| match operator {
+        ">=" => 3,
+        "^" => 2,
+        "~" => 1,
+        _ => 0,
+    };
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (raw, packages) in ranges {
+        match single_version(raw) {
+            Some((operator, _)) => *counts.entry(operator).or_default() += packages.len(),
+            None => return chosen.to_owned(),
+        }
+    }
+    match (
+        single_version(chosen),
+        counts
+            .into_iter()
+            .max_by_key(|&(operator, count)| (count, width(operator))),
+    ) {
+        (Some((_, version)), Some((operator, _))) => format!("{}{}", operator, version),
+        _ => chosen.to_owned(),
+    }
+}
+
+/**
+Finds every external dependency that's declared with more than one range across
+the project, apart from the allowed ones. Peer dependencies are left alone since
+they're usually meant to be wider than the ranges used for development.
+*/
+pub fn find_mismatches(
+    project: &Project,
+    config: &VersionsConfig,
+    policy: Policy,
+) -> Vec<Mismatch> {
+    let workspace_names: Vec<&PackageName> = project
+        .iter()
+        .filter_map(|pkg| pkg.pkg_json.name.as_ref())
+        .collect();
+    let mut dependencies: Vec<&PackageName> = project
+        .iter()
+        .flat_map(|pkg| pkg.pkg_json.iter_normal_deps())
+        .flat_map(|deps| deps.keys())
+        .filter(|name| !workspace_names.contains(name) && !config.allow.contains(name))
+        .collect();
+    dependencies.sort();
+    dependencies.dedup();
+    dependencies
+        .into_iter()
+        .filter_map(|name| {
+            let mut ranges = project.find_dependents(name);
+            if ranges.len() < 2 {
+                return None;
+            }
+            for packages in ranges.values_mut() {
+                packages.sort();
+                packages.dedup();
+            }
+            Some(Mismatch {
+                dependency: name.clone(),
+                fix: choose_range(&ranges, policy),
+                ranges,
+            })
+        })
+        .collect()
+}
+
+pub fn lint_versions(
+    project: &mut Project,
+    fix: bool,
+    policy: Option<Policy>,
+) -> anyhow::Result<()> {
+    let config = VersionsConfig::read(project)?;
+    let policy = policy.or(config.policy).unwrap_or(Policy::Highest);
+    let mismatches = find_mismatches(project, &config, policy);
+    if mismatches.is_empty() {
+        println!("Every dependency is declared with the same range everywhere");
+        return Ok(());
+    }
+    let mut unfixable = 0;
+    for mismatch in &mismatches {
+        println!(
+            "{} is declared with {} different ranges",
+            mismatch.dependency,
+            mismatch.ranges.len()
+        );
+        for (range, packages) in &mismatch.ranges {
+            let packages: Vec<_> = packages.iter().map(|key| key.to_string()).collect();
+            println!("  {} in {}", range, packages.join(", "));
+        }
+        match (&mismatch.fix, fix) {
+            (Some(range), true) => {
+                for pkg in project.iter_mut() {
+                    let pkg_json = &mut pkg.pkg_json;
+                    let mut changed = false;
+                    for deps in [
+                        &mut pkg_json.dependencies,
+                        &mut pkg_json.dev_dependencies,
+                        &mut pkg_json.optional_dependencies,
+                    ] {
+                        if let Some(existing) = deps.get_mut(&mismatch.dependency) {
+                            if existing != range {
+                                *existing = range.clone();
+                                changed = true;
+                            }
+                        }
+                    }
+                    if changed {
+                        pkg.write()?;
+                    }
+                }
+                println!("  Aligned to {}", range);
+            }
+            (Some(range), false) => println!("  Run with --fix to align to {}", range),
+            (None, _) => {
+                unfixable += 1;
+                println!("  Not every range is a semver range so it has to be aligned by hand");
+            }
+        }
+    }
+    if !fix {
+        anyhow::bail!(
+            "{} dependencies are declared with different ranges",
+            mismatches.len()
+        );
+    }
+    if unfixable != 0 {
+        anyhow::bail!("{} dependencies couldn't be aligned", unfixable);
+    }
+    println!("Run install to update the lockfile");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn fixture() -> Project {
+        let mut dir = env::current_dir().unwrap();
+        dir.push("fixtures/yarn");
+        Project::find(&dir, None).unwrap()
+    }
+
+    #[test]
+    fn react_mismatch() {
+        let project = fixture();
+        let react = PackageName::new("react".to_owned()).unwrap();
+        let app = PackageKey::Name(PackageName::new("@fixture/app".to_owned()).unwrap());
+        let lib = PackageKey::Name(PackageName::new("@fixture/lib".to_owned()).unwrap());
+        let mismatches = find_mismatches(&project, &VersionsConfig::default(), Policy::Highest);
+        assert_eq!(
+            mismatches,
+            vec![Mismatch {
+                dependency: react.clone(),
+                ranges: BTreeMap::from([
+                    ("^17.0.0".to_owned(), vec![app]),
+                    ("~17.0.1".to_owned(), vec![lib]),
+                ]),
+                // the newest version, without narrowing ^17.0.0
+                fix: Some("^17.0.1".to_owned()),
+            }]
+        );
+        let lowest = find_mismatches(&project, &VersionsConfig::default(), Policy::Lowest);
+        assert_eq!(lowest[0].fix, Some("^17.0.0".to_owned()));
+        let config = VersionsConfig {
+            policy: None,
+            allow: vec![react],
+        };
+        assert_eq!(find_mismatches(&project, &config, Policy::Highest), vec![]);
+    }
+
+    #[test]
+    fn keeps_the_common_operator() {
+        let pkg = |name: &str| PackageKey::Name(PackageName::new(name.to_owned()).unwrap());
+        let ranges = BTreeMap::from([
+            ("^1.2.0".to_owned(), vec![pkg("a"), pkg("b")]),
+            ("~1.3.0".to_owned(), vec![pkg("c")]),
+            ("1.1.0".to_owned(), vec![pkg("d")]),
+        ]);
+        assert_eq!(choose_range(&ranges, Policy::Highest).unwrap(), "^1.3.0");
+        assert_eq!(choose_range(&ranges, Policy::Lowest).unwrap(), "^1.1.0");
+        assert_eq!(choose_range(&ranges, Policy::MostCommon).unwrap(), "^1.2.0");
+
+        let mostly_tilde = BTreeMap::from([
+            ("^1.2.0".to_owned(), vec![pkg("a")]),
+            ("~1.3.0".to_owned(), vec![pkg("b"), pkg("c")]),
+        ]);
+        assert_eq!(
+            choose_range(&mostly_tilde, Policy::Lowest).unwrap(),
+            "~1.2.0"
+        );
+
+        // a range that isn't a single version is aligned to as it is
+        let wide = BTreeMap::from([
+            ("^1.2.0".to_owned(), vec![pkg("a")]),
+            (">=1.3.0 <3".to_owned(), vec![pkg("b")]),
+        ]);
+        assert_eq!(choose_range(&wide, Policy::Highest).unwrap(), ">=1.3.0 <3");
+    }
+
+    #[test]
+    fn unfixable_ranges() {
+        let ranges = BTreeMap::from([
+            ("^1.0.0".to_owned(), vec![]),
+            ("github:a/b".to_owned(), vec![]),
+        ]);
+        assert_eq!(choose_range(&ranges, Policy::Highest), None);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{
+    project::{relative_dir, Package, Project},
+    PackageManager, PackageName,
+};
+use anyhow::Context;
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
+    fs,
+};
+
+mod npm;
+mod pnpm;
+mod yarn;
+
+/// Splits a `name@range` style descriptor into the package name and the rest,
+/// taking care not to split on the `@` at the start of a scoped package name.
+pub fn split_descriptor(descriptor: &str) -> Option<(&str, &str)> {
+    let idx = descriptor.get(1..)?.find('@')? + 1;
+    Some((&descriptor[..idx], &descriptor[idx + 1..]))
+}
+
+#[derive(Debug, Clone)]
+pub struct LockedPackage {
+    pub name: PackageName,
+    pub version: String,
+    /// Indices into `Lockfile::packages` of the resolved dependencies
+    pub dependencies: Vec<usize>,
+    /// The ranges that were resolved to this version, as far as the lockfile records them
+    pub ranges: Vec<String>,
+}
+
+type Importer = BTreeMap<PackageName, usize>;
+
+/// The resolved dependency graph of a lockfile, in the same shape for every package manager.
+#[derive(Debug, Default)]
+pub struct Lockfile {
+    pub packages: Vec<LockedPackage>,
+    /// The resolved external dependencies of each workspace package, keyed by
+    /// its directory relative to the project root like `relative_dir`.
+    /// Links to other workspace packages aren't included.
+    pub importers: BTreeMap<String, Importer>,
+}
+
+/// The key of a workspace package in `Lockfile::importers`.
+pub fn importer_key(project: &Project, pkg: &Package) -> String {
+    relative_dir(project.dir(), pkg.path())
+}
+
+impl Lockfile {
+    pub fn load(project: &Project) -> anyhow::Result<Lockfile> {
+        let path = project.lockfile_path();
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read file at {}", path.display()))?;
+        match project.manager {
+            PackageManager::Yarn => yarn::parse(&contents, project),
+            PackageManager::PNPM => pnpm::parse(&contents),
+            PackageManager::NPM => npm::parse(&contents, project),
+        }
+        .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+    fn add_package(&mut self, name: &str, version: &str) -> anyhow::Result<usize> {
+        self.packages.push(LockedPackage {
+            name: PackageName::new(name.to_owned())?,
+            version: version.to_owned(),
+            dependencies: vec![],
+            ranges: vec![],
+        });
+        Ok(self.packages.len() - 1)
+    }
+    fn add_range(&mut self, idx: usize, range: &str) {
+        let ranges = &mut self.packages[idx].ranges;
+        if !ranges.iter().any(|existing| existing == range) {
+            ranges.push(range.to_owned());
+        }
+    }
+    pub fn importer(&self, project: &Project, pkg: &Package) -> Option<&Importer> {
+        self.importers.get(&importer_key(project, pkg))
+    }
+    /// Every package that is installed because of the given workspace dependencies.
+    pub fn reachable(&self, roots: &Importer) -> HashSet<usize> {
+        let mut reachable = HashSet::new();
+        let mut queue: Vec<usize> = roots.values().copied().collect();
+        while let Some(idx) = queue.pop() {
+            if reachable.insert(idx) {
+                queue.extend(&self.packages[idx].dependencies);
+            }
+        }
+        reachable
+    }
+    /**
+    Finds the shortest chain of dependencies from the given workspace
+    dependencies to each version of the package. Each chain starts with a direct
+    dependency and ends at the package. There can be exponentially many longer
+    ones in a graph full of diamonds, so they're left out.
+    */
+    pub fn paths_to(&self, roots: &Importer, name: &PackageName) -> Vec<Vec<usize>> {
+        let mut parents: HashMap<usize, Option<usize>> = HashMap::new();
+        let mut queue = VecDeque::new();
+        for &idx in roots.values() {
+            if let Entry::Vacant(entry) = parents.entry(idx) {
+                entry.insert(None);
+                queue.push_back(idx);
+            }
+        }
+        let mut paths = vec![];
+        while let Some(idx) = queue.pop_front() {
+            if &self.packages[idx].name == name {
+                let mut path = vec![idx];
+                while let Some(&Some(parent)) = parents.get(path.last().unwrap()) {
+                    path.push(parent);
+                }
+                path.reverse();
+                paths.push(path);
+                continue;
+            }
+            for &dep in &self.packages[idx].dependencies {
+                if let Entry::Vacant(entry) = parents.entry(dep) {
+                    entry.insert(Some(idx));
+                    queue.push_back(dep);
+                }
+            }
+        }
+        paths
+    }
+}
+
+/**
+Removes every entry for the given packages from the project's lockfile so the
+next install resolves them from scratch, leaving all other entries as they were.
+Returns the number of entries that were removed.
+*/
+pub fn prune(project: &Project, names: &[PackageName]) -> anyhow::Result<usize> {
+    let path = project.lockfile_path();
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let (pruned, removed) = match project.manager {
+        PackageManager::Yarn => yarn::prune(&contents, names),
+        PackageManager::PNPM => pnpm::prune(&contents, names),
+        PackageManager::NPM => npm::prune(&contents, names)
+            .with_context(|| format!("Failed to prune {}", path.display()))?,
+    };
+    if removed != 0 {
+        fs::write(&path, pruned)
+            .with_context(|| format!("Failed to write file at {}", path.display()))?;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortest_path_through_diamonds() {
+        // listing every path through 30 diamonds in a row would take 2^30 of them
+        let mut lockfile = Lockfile::default();
+        let root = lockfile.add_package("a-0", "1.0.0").unwrap();
+        let mut tops = vec![root];
+        for idx in 1..=30 {
+            let left = lockfile
+                .add_package(&format!("b-{}", idx), "1.0.0")
+                .unwrap();
+            let right = lockfile
+                .add_package(&format!("c-{}", idx), "1.0.0")
+                .unwrap();
+            let bottom = lockfile
+                .add_package(&format!("a-{}", idx), "1.0.0")
+                .unwrap();
+            lockfile.packages[*tops.last().unwrap()].dependencies = vec![left, right];
+            lockfile.packages[left].dependencies = vec![bottom];
+            lockfile.packages[right].dependencies = vec![bottom];
+            tops.push(bottom);
+        }
+        let react = lockfile.add_package("react", "17.0.2").unwrap();
+        let old_react = lockfile.add_package("react", "16.14.0").unwrap();
+        lockfile.packages[tops[30]].dependencies.push(react);
+        lockfile.packages[tops[29]].dependencies.push(old_react);
+
+        let name = PackageName::new("react".to_owned()).unwrap();
+        let mut roots = Importer::new();
+        roots.insert(PackageName::new("a-0".to_owned()).unwrap(), root);
+        let paths = lockfile.paths_to(&roots, &name);
+        assert_eq!(paths.len(), 2);
+        assert_eq!(*paths[0].last().unwrap(), old_react);
+        assert_eq!(paths[0].len(), 60);
+        assert_eq!(*paths[1].last().unwrap(), react);
+        assert_eq!(paths[1].len(), 62);
+        assert!(paths[1][..61]
+            .iter()
+            .step_by(2)
+            .all(|&idx| lockfile.packages[idx].name.as_str().starts_with("a-")));
+    }
+}
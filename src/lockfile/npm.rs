@@ -0,0 +1,300 @@
+use super::{importer_key, Lockfile};
+use crate::{
+    project::{relative_dir, Project},
+    PackageName,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+/// Removes the names from a v1 style nested `dependencies` tree, returning how many entries were removed.
+fn prune_dependency_tree(dependencies: &mut Map<String, Value>, names: &[PackageName]) -> usize {
+    let removed = retain(dependencies, |key| {
+        !names.iter().any(|pkg| pkg.as_str() == key)
+    });
+    removed
+        + dependencies
+            .values_mut()
+            .filter_map(|dependency| match dependency.get_mut("dependencies") {
+                Some(Value::Object(nested)) => Some(prune_dependency_tree(nested, names)),
+                _ => None,
+            })
+            .sum::<usize>()
+}
+
+/// Keeps the entries whose keys match the predicate without disturbing the
+/// order of the others (`Map::remove` swaps the last entry into the gap).
+fn retain(map: &mut Map<String, Value>, keep: impl Fn(&str) -> bool) -> usize {
+    let len = map.len();
+    *map = std::mem::take(map)
+        .into_iter()
+        .filter(|(key, _)| keep(key))
+        .collect();
+    len - map.len()
+}
+
+/// Whether a `packages` key like `node_modules/a/node_modules/react` is an install location of one of the names.
+fn is_install_location(key: &str, names: &[PackageName]) -> bool {
+    key.rsplit_once("node_modules/")
+        .is_some_and(|(_, name)| names.iter().any(|pkg| pkg.as_str() == name))
+}
+
+pub(super) fn prune(contents: &str, names: &[PackageName]) -> anyhow::Result<(String, usize)> {
+    let mut lockfile: Value = serde_json::from_str(contents)?;
+    let mut removed = 0;
+    if let Some(Value::Object(packages)) = lockfile.get_mut("packages") {
+        let locations: Vec<String> = packages
+            .keys()
+            .filter(|key| is_install_location(key, names))
+            .cloned()
+            .collect();
+        // anything nested inside a removed location belongs to it and goes too
+        retain(packages, |key| {
+            !locations
+                .iter()
+                .any(|location| key == location || key.starts_with(&format!("{}/", location)))
+        });
+        removed += locations.len();
+    }
+    if let Some(Value::Object(dependencies)) = lockfile.get_mut("dependencies") {
+        removed += prune_dependency_tree(dependencies, names);
+    }
+    let mut stringified = serde_json::to_string_pretty(&lockfile)?;
+    stringified.push('\n');
+    Ok((stringified, removed))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct NpmPackage {
+    name: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    link: bool,
+    #[serde(default)]
+    dependencies: BTreeMap<String, Value>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, Value>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<String, Value>,
+    /// The dependency ranges of an entry in a v1 lockfile
+    #[serde(default)]
+    requires: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NpmLockfile {
+    packages: Option<BTreeMap<String, NpmPackage>>,
+    /// The nested dependency tree of a v1 lockfile
+    #[serde(default)]
+    dependencies: BTreeMap<String, V1Dependency>,
+}
+
+#[derive(Deserialize)]
+struct V1Dependency {
+    version: String,
+    #[serde(default)]
+    requires: BTreeMap<String, Value>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, V1Dependency>,
+}
+
+/// Flattens a v1 dependency tree into the install locations used by the `packages` of later lockfiles.
+fn flatten_v1_tree(
+    parent: &str,
+    tree: BTreeMap<String, V1Dependency>,
+    packages: &mut BTreeMap<String, NpmPackage>,
+) {
+    for (name, dependency) in tree {
+        let location = if parent.is_empty() {
+            format!("node_modules/{}", name)
+        } else {
+            format!("{}/node_modules/{}", parent, name)
+        };
+        flatten_v1_tree(&location, dependency.dependencies, packages);
+        packages.insert(
+            location,
+            NpmPackage {
+                version: Some(dependency.version),
+                requires: dependency.requires,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+/// Resolves a dependency the way node does, looking in the `node_modules` of each parent directory.
+fn resolve_location<'a>(
+    packages: &'a BTreeMap<String, NpmPackage>,
+    mut from: &str,
+    name: &str,
+) -> Option<&'a str> {
+    loop {
+        let location = if from.is_empty() {
+            format!("node_modules/{}", name)
+        } else {
+            format!("{}/node_modules/{}", from, name)
+        };
+        if let Some((key, _)) = packages.get_key_value(&location) {
+            return Some(key);
+        }
+        if from.is_empty() {
+            return None;
+        }
+        from = from.rfind('/').map_or("", |idx| &from[..idx]);
+    }
+}
+
+pub(super) fn parse(contents: &str, project: &Project) -> anyhow::Result<Lockfile> {
+    let npm_lockfile: NpmLockfile = serde_json::from_str(contents)?;
+    let packages = match npm_lockfile.packages {
+        Some(packages) => packages,
+        None => {
+            let mut packages = BTreeMap::new();
+            flatten_v1_tree("", npm_lockfile.dependencies, &mut packages);
+            // v1 lockfiles don't record the root so take its ranges from package.json
+            let root = &project.root.pkg_json;
+            let requires = root
+                .iter_normal_deps()
+                .flatten()
+                .map(|(name, range)| (name.to_string(), Value::String(range.clone())))
+                .collect();
+            packages.insert(
+                String::new(),
+                NpmPackage {
+                    requires,
+                    ..Default::default()
+                },
+            );
+            packages
+        }
+    };
+
+    let mut lockfile = Lockfile::default();
+    let mut by_location = HashMap::new();
+    for (location, pkg) in &packages {
+        if pkg.link {
+            continue;
+        }
+        if let Some((_, name)) = location.rsplit_once("node_modules/") {
+            let name = pkg.name.as_deref().unwrap_or(name);
+            let version = pkg.version.as_deref().unwrap_or_default();
+            by_location.insert(location.as_str(), lockfile.add_package(name, version)?);
+        }
+    }
+    let workspace_dirs: Vec<String> = project
+        .iter()
+        .map(|pkg| importer_key(project, pkg))
+        .collect();
+    for (location, pkg) in &packages {
+        let resolved: Vec<(&String, usize)> = pkg
+            .dependencies
+            .iter()
+            .chain(&pkg.optional_dependencies)
+            .chain(&pkg.requires)
+            .chain(&pkg.dev_dependencies)
+            .filter_map(|(name, range)| {
+                let dep = resolve_location(&packages, location, name)?;
+                let &idx = by_location.get(dep)?;
+                if let Some(range) = range.as_str() {
+                    lockfile.add_range(idx, range);
+                }
+                Some((name, idx))
+            })
+            .collect();
+        match by_location.get(location.as_str()) {
+            Some(&idx) => {
+                lockfile.packages[idx].dependencies =
+                    resolved.into_iter().map(|(_, dep)| dep).collect()
+            }
+            None => {
+                // the root's location is ""
+                let key = relative_dir(Path::new(""), Path::new(location));
+                if !workspace_dirs.contains(&key) {
+                    continue;
+                }
+                let mut importer = BTreeMap::new();
+                for (name, dep) in resolved {
+                    importer.insert(PackageName::new(name.clone())?, dep);
+                }
+                lockfile.importers.insert(key, importer);
+            }
+        }
+    }
+    Ok(lockfile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_v2_lockfile() {
+        let contents = r#"{
+  "name": "something",
+  "lockfileVersion": 2,
+  "packages": {
+    "": {
+      "name": "something",
+      "dependencies": {
+        "react": "^17.0.0"
+      }
+    },
+    "node_modules/object-assign": {
+      "version": "4.1.1"
+    },
+    "node_modules/react": {
+      "version": "17.0.2"
+    },
+    "node_modules/react/node_modules/object-assign": {
+      "version": "4.1.0"
+    }
+  },
+  "dependencies": {
+    "object-assign": {
+      "version": "4.1.1"
+    },
+    "react": {
+      "version": "17.0.2",
+      "dependencies": {
+        "object-assign": {
+          "version": "4.1.0"
+        }
+      }
+    }
+  }
+}
+"#;
+        let react = PackageName::new("react".to_owned()).unwrap();
+        let (pruned, removed) = prune(contents, &[react]).unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(
+            pruned,
+            r#"{
+  "name": "something",
+  "lockfileVersion": 2,
+  "packages": {
+    "": {
+      "name": "something",
+      "dependencies": {
+        "react": "^17.0.0"
+      }
+    },
+    "node_modules/object-assign": {
+      "version": "4.1.1"
+    }
+  },
+  "dependencies": {
+    "object-assign": {
+      "version": "4.1.1"
+    }
+  }
+}
+"#
+        );
+    }
+}
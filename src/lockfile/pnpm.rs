@@ -0,0 +1,605 @@
+use super::{split_descriptor, Lockfile};
+use crate::PackageName;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+const DEPENDENCY_SECTIONS: [&str; 4] = [
+    "specifiers",
+    "dependencies",
+    "devDependencies",
+    "optionalDependencies",
+];
+
+/// Parses a line of a block-style YAML mapping into its indentation and
+/// unquoted key, returning `None` for blank lines, comments and list items.
+fn mapping_key(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('-') {
+        return None;
+    }
+    let indent = line.len() - trimmed.len();
+    let key = match trimmed.chars().next() {
+        Some(quote @ ('\'' | '"')) => {
+            let end = trimmed[1..].find(quote)? + 1;
+            &trimmed[1..end]
+        }
+        _ => {
+            let end = trimmed
+                .match_indices(':')
+                .map(|(idx, _)| idx)
+                .find(|&idx| {
+                    matches!(
+                        trimmed.as_bytes().get(idx + 1),
+                        None | Some(b' ' | b'\n' | b'\r')
+                    )
+                })?;
+            &trimmed[..end]
+        }
+    };
+    Some((indent, key))
+}
+
+fn has_inline_value(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.ends_with(':')
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/**
+Removes every mapping entry (including its nested block and trailing blank
+lines) whose key path and text, its line and nested block, match the predicate. Dependency sections that only
+contained removed entries are dropped, and other mappings that were emptied
+are written as `{}` so pnpm doesn't read them as null.
+*/
+fn remove_entries(
+    contents: &str,
+    should_remove: impl Fn(&[&str], &str) -> bool,
+) -> (String, usize) {
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let mut kept: Vec<usize> = vec![];
+    let mut path: Vec<(usize, &str)> = vec![];
+    let mut skip_deeper_than = None;
+    let mut skipped_blanks = vec![];
+    let mut removed = 0;
+    for (idx, line) in lines.iter().enumerate() {
+        if let Some(skip_indent) = skip_deeper_than {
+            if line.trim().is_empty() {
+                skipped_blanks.push(idx);
+                continue;
+            }
+            if indentation(line) > skip_indent {
+                skipped_blanks.clear();
+                continue;
+            }
+            // blank lines only separate the removed entry from a sibling,
+            // otherwise they separate its parent from what comes next, unless
+            // the blank line before the removed entry already does
+            let blank_before = kept
+                .last()
+                .is_some_and(|&kept| lines[kept].trim().is_empty());
+            if indentation(line) < skip_indent && !blank_before {
+                kept.append(&mut skipped_blanks);
+            }
+            skipped_blanks.clear();
+            skip_deeper_than = None;
+        }
+        if let Some((indent, key)) = mapping_key(line) {
+            while path.last().is_some_and(|&(parent, _)| parent >= indent) {
+                path.pop();
+            }
+            path.push((indent, key));
+            let keys: Vec<&str> = path.iter().map(|&(_, key)| key).collect();
+            let block_len = lines[idx + 1..]
+                .iter()
+                .take_while(|line| line.trim().is_empty() || indentation(line) > indent)
+                .count();
+            if should_remove(&keys, &lines[idx..=idx + block_len].concat()) {
+                removed += 1;
+                skip_deeper_than = Some(indent);
+                continue;
+            }
+        }
+        kept.push(idx);
+    }
+
+    // walk backwards so emptied sections are known before their parents are written
+    let mut emitted: Vec<std::borrow::Cow<str>> = vec![];
+    for (pos, &idx) in kept.iter().enumerate().rev() {
+        let line = lines[idx];
+        if let Some((indent, key)) = mapping_key(line) {
+            let child_indent = |mut following: std::slice::Iter<&str>| {
+                following
+                    .find(|line| !line.trim().is_empty())
+                    .is_some_and(|line| indentation(line) > indent)
+            };
+            let had_children = !has_inline_value(line) && child_indent(lines[idx + 1..].iter());
+            let has_children = emitted
+                .iter()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .is_some_and(|line| indentation(line) > indent);
+            if had_children && !has_children {
+                if !DEPENDENCY_SECTIONS.contains(&key) {
+                    emitted.push(format!("{} {{}}\n", line.trim_end()).into());
+                } else if pos > 0
+                    && lines[kept[pos - 1]].trim().is_empty()
+                    && emitted.last().is_some_and(|next| next.trim().is_empty())
+                {
+                    // the blank line before the section is enough to separate what's around it
+                    emitted.pop();
+                }
+                continue;
+            }
+        }
+        emitted.push(line.into());
+    }
+    let mut pruned: String = emitted.into_iter().rev().collect();
+    // dropping the last entry leaves the separator of the one before it behind
+    if removed != 0 && !contents.ends_with("\n\n") {
+        pruned.truncate(pruned.trim_end_matches('\n').len());
+        pruned.push('\n');
+    }
+    (pruned, removed)
+}
+
+fn lockfile_major_version(contents: &str) -> u32 {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("lockfileVersion:"))
+        .and_then(|version| {
+            version
+                .trim()
+                .trim_matches(|c| c == '\'' || c == '"')
+                .split('.')
+                .next()?
+                .parse()
+                .ok()
+        })
+        .unwrap_or(5)
+}
+
+/// Gets the package name out of a `packages`/`snapshots` key, which is
+/// `/name/version` before lockfile v6 and `/name@version` (or `name@version`) after.
+pub(super) fn package_name_from_key(key: &str, major_version: u32) -> Option<&str> {
+    let key = key.strip_prefix('/').unwrap_or(key);
+    if major_version < 6 {
+        key.rfind('/').map(|idx| &key[..idx])
+    } else {
+        split_descriptor(key).map(|(name, _)| name)
+    }
+}
+
+/// The names of the peer dependencies in the `(react@18.2.0)` style suffixes of lockfile v6 and later
+fn peer_suffix_names(text: &str) -> impl Iterator<Item = &str> {
+    text.match_indices('(')
+        .filter_map(|(idx, _)| split_descriptor(&text[idx + 1..]).map(|(name, _)| name))
+}
+
+/**
+Removes the entries of the packages along with every reference to them, in the
+dependencies of the workspace packages and of other entries, since those point
+at resolved versions that wouldn't exist anymore. Since lockfile v6 the
+versions of packages with peer dependencies have the versions of the peers in
+them, so those entries and references go as well.
+*/
+pub(super) fn prune(contents: &str, names: &[PackageName]) -> (String, usize) {
+    let major_version = lockfile_major_version(contents);
+    let is_target = |name: &str| names.iter().any(|pkg| pkg.as_str() == name);
+    let resolved_with_target = |text: &str| peer_suffix_names(text).any(is_target);
+    remove_entries(contents, |keys, entry| match keys {
+        ["packages" | "snapshots", key] => {
+            package_name_from_key(key, major_version).is_some_and(is_target)
+                || resolved_with_target(key)
+        }
+        ["importers", _, section, name] | [section, name] => {
+            DEPENDENCY_SECTIONS.contains(section)
+                && (is_target(name) || resolved_with_target(entry))
+        }
+        ["packages" | "snapshots", _, "dependencies" | "optionalDependencies", name] => {
+            is_target(name) || resolved_with_target(entry)
+        }
+        _ => false,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ImporterDependency {
+    Version(String),
+    Detailed { specifier: String, version: String },
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct PnpmImporter {
+    /// The ranges of the dependencies before lockfile v6
+    #[serde(default)]
+    specifiers: BTreeMap<String, String>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, ImporterDependency>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<String, ImporterDependency>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, ImporterDependency>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PnpmPackage {
+    name: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    peer_dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PnpmLockfile {
+    importers: Option<BTreeMap<String, PnpmImporter>>,
+    #[serde(flatten)]
+    root: PnpmImporter,
+    #[serde(default)]
+    packages: BTreeMap<String, PnpmPackage>,
+    /// Lockfile v9 moved the dependencies of each package here
+    snapshots: Option<BTreeMap<String, PnpmPackage>>,
+}
+
+/// Turns a resolved version as written in a dependency list into the key of its
+/// entry, e.g. `17.0.2` into `/react/17.0.2` (v5), `/react@17.0.2` (v6) or `react@17.0.2` (v9).
+fn dependency_key(name: &str, version: &str, major_version: u32) -> Option<String> {
+    if version.starts_with("link:") || version.starts_with("file:") {
+        return None;
+    }
+    // aliased dependencies are written as a full key
+    if version.starts_with('/') || (major_version >= 9 && split_descriptor(version).is_some()) {
+        return Some(version.to_owned());
+    }
+    Some(match major_version {
+        0..=5 => format!("/{}/{}", name, version),
+        6..=8 => format!("/{}@{}", name, version),
+        _ => format!("{}@{}", name, version),
+    })
+}
+
+/// Strips the peer dependency suffix off of the version in a key
+fn version_from_key(key: &str, name: &str, major_version: u32) -> String {
+    let key = key.strip_prefix('/').unwrap_or(key);
+    let version = key.get(name.len() + 1..).unwrap_or_default();
+    let end = if major_version < 6 {
+        version.find('_')
+    } else {
+        version.find('(')
+    };
+    version[..end.unwrap_or(version.len())].to_owned()
+}
+
+pub(super) fn parse(contents: &str) -> anyhow::Result<Lockfile> {
+    let major_version = lockfile_major_version(contents);
+    let pnpm_lockfile: PnpmLockfile = serde_yaml::from_str(contents)?;
+    let packages = pnpm_lockfile.packages;
+    let entries = pnpm_lockfile.snapshots.as_ref().unwrap_or(&packages);
+
+    let mut lockfile = Lockfile::default();
+    let mut by_key = HashMap::new();
+    for (key, entry) in entries {
+        // packages that don't come from the registry have their name and version written out
+        let name = match entry.name.as_deref() {
+            Some(name) => name,
+            None => match package_name_from_key(key, major_version) {
+                Some(name) => name,
+                None => anyhow::bail!("Invalid package key {}", key),
+            },
+        };
+        let version = match &entry.version {
+            Some(version) => version.clone(),
+            None => version_from_key(key, name, major_version),
+        };
+        by_key.insert(key.as_str(), lockfile.add_package(name, &version)?);
+    }
+    let resolve = |name: &str, version: &str| {
+        dependency_key(name, version, major_version)
+            .and_then(|key| by_key.get(key.as_str()).copied())
+    };
+    for (idx, (key, entry)) in entries.iter().enumerate() {
+        // the resolved peer dependencies are left out like npm and Yarn leave them out,
+        // since v9 they're declared in `packages` under the key without the peer suffix
+        let peers = packages
+            .get(&key[..key.find('(').unwrap_or(key.len())])
+            .unwrap_or(entry);
+        lockfile.packages[idx].dependencies = entry
+            .dependencies
+            .iter()
+            .chain(&entry.optional_dependencies)
+            .filter(|(name, _)| !peers.peer_dependencies.contains_key(*name))
+            .filter_map(|(name, version)| resolve(name, version))
+            .collect();
+    }
+
+    let importers = match pnpm_lockfile.importers {
+        Some(importers) => importers,
+        None => std::iter::once((".".to_owned(), pnpm_lockfile.root)).collect(),
+    };
+    for (path, importer) in importers {
+        let mut resolved = BTreeMap::new();
+        for (name, dependency) in importer
+            .dependencies
+            .iter()
+            .chain(&importer.dev_dependencies)
+            .chain(&importer.optional_dependencies)
+        {
+            let (specifier, version) = match dependency {
+                ImporterDependency::Version(version) => (importer.specifiers.get(name), version),
+                ImporterDependency::Detailed { specifier, version } => (Some(specifier), version),
+            };
+            if let Some(idx) = resolve(name, version) {
+                resolved.insert(PackageName::new(name.clone())?, idx);
+                if let Some(specifier) = specifier {
+                    lockfile.add_range(idx, specifier);
+                }
+            }
+        }
+        lockfile.importers.insert(path, resolved);
+    }
+    Ok(lockfile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_v6_lockfile() {
+        let contents = "lockfileVersion: '6.0'
+
+importers:
+
+  .:
+    dependencies:
+      react:
+        specifier: ^17.0.0
+        version: 17.0.2
+
+  packages/a:
+    dependencies:
+      object-assign:
+        specifier: ^4.1.1
+        version: 4.1.1
+      react:
+        specifier: ~17.0.1
+        version: 17.0.1
+
+packages:
+
+  /object-assign@4.1.1:
+    resolution: {integrity: sha1-IQmtx5ZYh8/AXLvUQsrIv7s2CGM=}
+    engines: {node: '>=0.10.0'}
+    dev: false
+
+  /react@17.0.1:
+    resolution: {integrity: sha512-lG9c9UuMHdcAexXtigOZLX8exLWkW0Ku29qPRU8uhF2R9BN96dLCt0psvzPLlHc5OWkgymP3qwTRgbnw5BKx3w==}
+    dependencies:
+      object-assign: 4.1.1
+    dev: false
+
+  /react@17.0.2:
+    resolution: {integrity: sha512-gnhPt75i/dq/z3/6q/0asP78D0u592D5L1pd7M8P+dck6Fu/jJeL6iVVK23fptSUZj8Vjf++7wXA8UNclGQcbA==}
+    dev: false
+";
+        let react = PackageName::new("react".to_owned()).unwrap();
+        let (pruned, removed) = prune(contents, &[react]);
+        assert_eq!(removed, 4);
+        assert_eq!(
+            pruned,
+            "lockfileVersion: '6.0'
+
+importers:
+
+  .: {}
+
+  packages/a:
+    dependencies:
+      object-assign:
+        specifier: ^4.1.1
+        version: 4.1.1
+
+packages:
+
+  /object-assign@4.1.1:
+    resolution: {integrity: sha1-IQmtx5ZYh8/AXLvUQsrIv7s2CGM=}
+    engines: {node: '>=0.10.0'}
+    dev: false
+"
+        );
+    }
+
+    #[test]
+    fn prune_references_from_other_packages() {
+        let contents = "lockfileVersion: '6.0'
+
+dependencies:
+  react-dom:
+    specifier: ^17.0.2
+    version: 17.0.2(react@17.0.2)
+
+packages:
+
+  /loose-envify@1.4.0:
+    resolution: {integrity: sha512-lyuxPGr/Wfhrlem2CL/UcnUc1zcqKAImBDzukY7Y5F/yQiNdko6+fRLevlw1HgMySw7f611UIY408EtxRSoK3Q==}
+    dev: false
+
+  /react-dom@17.0.2(react@17.0.2):
+    resolution: {integrity: sha512-s4h96KtLDUQlsENhMn1ar8t2bEa+q/YAtj8pPPdIjPDGBDIVNsrD9aXNWqspUe6AzKCIG0C1HZZLqLV7qpOBGA==}
+    peerDependencies:
+      react: 17.0.2
+    dependencies:
+      loose-envify: 1.4.0
+      react: 17.0.2
+    dev: false
+
+  /react@17.0.2:
+    resolution: {integrity: sha512-gnhPt75i/dq/z3/6q/0asP78D0u592D5L1pd7M8P+dck6Fu/jJeL6iVVK23fptSUZj8Vjf++7wXA8UNclGQcbA==}
+    dependencies:
+      loose-envify: 1.4.0
+    dev: false
+";
+        let react = PackageName::new("react".to_owned()).unwrap();
+        let (pruned, removed) = prune(contents, &[react]);
+        assert_eq!(removed, 3);
+        // react-dom was resolved with the version of react that's gone
+        assert_eq!(
+            pruned,
+            "lockfileVersion: '6.0'
+
+packages:
+
+  /loose-envify@1.4.0:
+    resolution: {integrity: sha512-lyuxPGr/Wfhrlem2CL/UcnUc1zcqKAImBDzukY7Y5F/yQiNdko6+fRLevlw1HgMySw7f611UIY408EtxRSoK3Q==}
+    dev: false
+"
+        );
+    }
+
+    #[test]
+    fn prune_v9_peer_suffixes() {
+        let contents = "lockfileVersion: '9.0'
+
+settings:
+  autoInstallPeers: true
+  excludeLinksFromLockfile: false
+
+importers:
+
+  .:
+    dependencies:
+      '@testing-library/react':
+        specifier: ^14.0.0
+        version: 14.0.0(react-dom@18.2.0(react@18.2.0))(react@18.2.0)
+      lodash:
+        specifier: ^4.17.21
+        version: 4.17.21
+      react:
+        specifier: ^18.2.0
+        version: 18.2.0
+      react-dom:
+        specifier: ^18.2.0
+        version: 18.2.0(react@18.2.0)
+
+packages:
+
+  '@testing-library/react@14.0.0':
+    resolution: {integrity: sha512-testing}
+    peerDependencies:
+      react: ^18.0.0
+      react-dom: ^18.0.0
+
+  lodash@4.17.21:
+    resolution: {integrity: sha512-lodash}
+
+  loose-envify@1.4.0:
+    resolution: {integrity: sha512-loose}
+    hasBin: true
+
+  react-dom@18.2.0:
+    resolution: {integrity: sha512-react-dom}
+    peerDependencies:
+      react: ^18.2.0
+
+  react@18.2.0:
+    resolution: {integrity: sha512-react}
+
+snapshots:
+
+  '@testing-library/react@14.0.0(react-dom@18.2.0(react@18.2.0))(react@18.2.0)':
+    dependencies:
+      react: 18.2.0
+      react-dom: 18.2.0(react@18.2.0)
+
+  lodash@4.17.21: {}
+
+  loose-envify@1.4.0: {}
+
+  react-dom@18.2.0(react@18.2.0):
+    dependencies:
+      loose-envify: 1.4.0
+      react: 18.2.0
+
+  react@18.2.0:
+    dependencies:
+      loose-envify: 1.4.0
+";
+        let react = PackageName::new("react".to_owned()).unwrap();
+        let (pruned, removed) = prune(contents, &[react]);
+        assert_eq!(removed, 7);
+        // the packages entries of the peers' dependents don't have the versions in their keys
+        assert_eq!(
+            pruned,
+            "lockfileVersion: '9.0'
+
+settings:
+  autoInstallPeers: true
+  excludeLinksFromLockfile: false
+
+importers:
+
+  .:
+    dependencies:
+      lodash:
+        specifier: ^4.17.21
+        version: 4.17.21
+
+packages:
+
+  '@testing-library/react@14.0.0':
+    resolution: {integrity: sha512-testing}
+    peerDependencies:
+      react: ^18.0.0
+      react-dom: ^18.0.0
+
+  lodash@4.17.21:
+    resolution: {integrity: sha512-lodash}
+
+  loose-envify@1.4.0:
+    resolution: {integrity: sha512-loose}
+    hasBin: true
+
+  react-dom@18.2.0:
+    resolution: {integrity: sha512-react-dom}
+    peerDependencies:
+      react: ^18.2.0
+
+snapshots:
+
+  lodash@4.17.21: {}
+
+  loose-envify@1.4.0: {}
+"
+        );
+    }
+
+    #[test]
+    fn package_names_from_keys() {
+        assert_eq!(
+            package_name_from_key("/@babel/core/7.0.0", 5),
+            Some("@babel/core")
+        );
+        assert_eq!(
+            package_name_from_key("/react/17.0.2_react-dom@17.0.2", 5),
+            Some("react")
+        );
+        assert_eq!(
+            package_name_from_key("/@babel/core@7.0.0", 6),
+            Some("@babel/core")
+        );
+        assert_eq!(
+            package_name_from_key("react@17.0.2(react-dom@17.0.2)", 9),
+            Some("react")
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use super::{importer_key, split_descriptor, Lockfile};
+use crate::{
+    project::{relative_dir, Project},
+    PackageName,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+/// Returns the descriptors (e.g. `react@^17.0.0`) listed in an entry's header line.
+fn header_descriptors(header: &str) -> impl Iterator<Item = &str> {
+    header
+        .trim_end()
+        .trim_end_matches(':')
+        .split(", ")
+        .map(|descriptor| descriptor.trim_matches('"'))
+}
+
+fn is_entry_header(line: &str) -> bool {
+    !line.is_empty() && !line.starts_with(char::is_whitespace) && !line.starts_with('#')
+}
+
+/// Splits a yarn.lock into the leading comments and its entries, each entry
+/// keeping the blank lines that follow it so entries can be dropped cleanly.
+fn split_entries(contents: &str) -> (&str, Vec<&str>) {
+    let mut starts = vec![];
+    let mut offset = 0;
+    for line in contents.split_inclusive('\n') {
+        if is_entry_header(line) {
+            starts.push(offset);
+        }
+        offset += line.len();
+    }
+    let preamble_end = starts.first().copied().unwrap_or(contents.len());
+    let entries = starts
+        .iter()
+        .zip(
+            starts
+                .iter()
+                .skip(1)
+                .chain(std::iter::once(&contents.len())),
+        )
+        .map(|(&start, &end)| &contents[start..end])
+        .collect();
+    (&contents[..preamble_end], entries)
+}
+
+pub(super) fn prune(contents: &str, names: &[PackageName]) -> (String, usize) {
+    let (preamble, entries) = split_entries(contents);
+    let mut pruned = preamble.to_owned();
+    let mut removed = 0;
+    for entry in entries {
+        let header = entry.lines().next().unwrap_or_default();
+        let matches = header_descriptors(header).any(|descriptor| {
+            split_descriptor(descriptor)
+                .map(|(name, _)| names.iter().any(|pkg| pkg.as_str() == name))
+                .unwrap_or(false)
+        });
+        if matches {
+            removed += 1;
+        } else {
+            pruned.push_str(entry);
+        }
+    }
+    // dropping the last entry leaves the separator of the one before it behind
+    if removed != 0 && !contents.ends_with("\n\n") {
+        pruned.truncate(pruned.trim_end_matches('\n').len());
+        pruned.push('\n');
+    }
+    (pruned, removed)
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim().trim_matches('"')
+}
+
+/// Splits a `key value` line of a v1 lockfile where either part may be quoted.
+fn split_key_value(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    let key_end = if let Some(rest) = line.strip_prefix('"') {
+        rest.find('"')? + 2
+    } else {
+        line.find(' ')?
+    };
+    Some((unquote(&line[..key_end]), unquote(&line[key_end..])))
+}
+
+#[derive(Default)]
+struct V1Entry<'a> {
+    descriptors: Vec<&'a str>,
+    version: &'a str,
+    dependencies: Vec<(&'a str, &'a str)>,
+}
+
+fn parse_v1_entries(contents: &str) -> Vec<V1Entry<'_>> {
+    let mut entries: Vec<V1Entry> = vec![];
+    let mut in_dependencies = false;
+    for line in contents.lines() {
+        if is_entry_header(line) {
+            entries.push(V1Entry {
+                descriptors: header_descriptors(line).collect(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let entry = match entries.last_mut() {
+            Some(entry) => entry,
+            None => continue,
+        };
+        if line.starts_with("    ") {
+            if in_dependencies {
+                if let Some(dependency) = split_key_value(line) {
+                    entry.dependencies.push(dependency);
+                }
+            }
+        } else if let Some(field) = line.strip_prefix("  ") {
+            in_dependencies = matches!(field, "dependencies:" | "optionalDependencies:");
+            if let Some(("version", version)) = split_key_value(field) {
+                entry.version = version;
+            }
+        }
+    }
+    entries
+}
+
+fn parse_v1(contents: &str, project: &Project) -> anyhow::Result<Lockfile> {
+    let entries = parse_v1_entries(contents);
+    let mut lockfile = Lockfile::default();
+    let mut by_descriptor = HashMap::new();
+    for entry in &entries {
+        let name = match entry.descriptors.first().and_then(|d| split_descriptor(d)) {
+            Some((name, _)) => name,
+            None => anyhow::bail!("Invalid entry {:?}", entry.descriptors),
+        };
+        let idx = lockfile.add_package(name, entry.version)?;
+        for &descriptor in &entry.descriptors {
+            by_descriptor.insert(descriptor.to_owned(), idx);
+            if let Some((_, range)) = split_descriptor(descriptor) {
+                lockfile.add_range(idx, range);
+            }
+        }
+    }
+    for (idx, entry) in entries.iter().enumerate() {
+        lockfile.packages[idx].dependencies = entry
+            .dependencies
+            .iter()
+            .filter_map(|(name, range)| by_descriptor.get(&format!("{}@{}", name, range)))
+            .copied()
+            .collect();
+    }
+    // v1 lockfiles don't record workspaces so resolve them the way yarn does,
+    // from the ranges in each package.json
+    for pkg in project.iter() {
+        let mut importer = BTreeMap::new();
+        for deps in pkg.pkg_json.iter_normal_deps() {
+            for (name, range) in deps {
+                if let Some(&idx) = by_descriptor.get(&format!("{}@{}", name, range)) {
+                    importer.insert(name.clone(), idx);
+                }
+            }
+        }
+        lockfile
+            .importers
+            .insert(importer_key(project, pkg), importer);
+    }
+    Ok(lockfile)
+}
+
+#[derive(Deserialize)]
+struct BerryEntry {
+    version: String,
+    resolution: String,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+}
+
+/// Parses the lockfiles of Yarn 2 and later, which are YAML and list workspaces as entries.
+fn parse_berry(contents: &str) -> anyhow::Result<Lockfile> {
+    let raw: BTreeMap<String, serde_yaml::Value> = serde_yaml::from_str(contents)?;
+    let mut entries = vec![];
+    for (key, value) in raw {
+        if key != "__metadata" {
+            entries.push((key, serde_yaml::from_value::<BerryEntry>(value)?));
+        }
+    }
+    let mut lockfile = Lockfile::default();
+    let mut by_descriptor = HashMap::new();
+    let mut indices = vec![];
+    for (key, entry) in &entries {
+        let idx = match entry.resolution.split_once("@workspace:") {
+            Some(_) => None,
+            None => {
+                let name = match split_descriptor(&entry.resolution) {
+                    Some((name, _)) => name,
+                    None => anyhow::bail!("Invalid resolution {}", entry.resolution),
+                };
+                Some(lockfile.add_package(name, &entry.version)?)
+            }
+        };
+        for descriptor in header_descriptors(key) {
+            by_descriptor.insert(descriptor.to_owned(), idx);
+            if let (Some(idx), Some((_, range))) = (idx, split_descriptor(descriptor)) {
+                lockfile.add_range(idx, range.strip_prefix("npm:").unwrap_or(range));
+            }
+        }
+        indices.push(idx);
+    }
+    for ((_, entry), idx) in entries.iter().zip(indices) {
+        let dependencies = entry.dependencies.iter().filter_map(|(name, range)| {
+            // ranges without a protocol are written without the implied `npm:`
+            by_descriptor
+                .get(&format!("{}@{}", name, range))
+                .or_else(|| by_descriptor.get(&format!("{}@npm:{}", name, range)))
+                .copied()
+                .flatten()
+                .map(|dep| (name, dep))
+        });
+        match idx {
+            Some(idx) => {
+                lockfile.packages[idx].dependencies = dependencies.map(|(_, dep)| dep).collect()
+            }
+            None => {
+                let mut importer = BTreeMap::new();
+                for (name, dep) in dependencies {
+                    importer.insert(PackageName::new(name.clone())?, dep);
+                }
+                let path = entry.resolution.split_once("@workspace:").unwrap().1;
+                lockfile
+                    .importers
+                    .insert(relative_dir(Path::new(""), Path::new(path)), importer);
+            }
+        }
+    }
+    Ok(lockfile)
+}
+
+pub(super) fn parse(contents: &str, project: &Project) -> anyhow::Result<Lockfile> {
+    if contents.contains("\n__metadata:") {
+        parse_berry(contents)
+    } else {
+        parse_v1(contents, project)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_basic_fixture() {
+        let contents = std::fs::read_to_string("fixtures/basic/yarn.lock").unwrap();
+        let react = PackageName::new("react".to_owned()).unwrap();
+        let (pruned, removed) = prune(&contents, &[react]);
+        assert_eq!(removed, 3);
+        assert!(!pruned.contains("react@"));
+        assert!(pruned.ends_with("integrity sha1-IQmtx5ZYh8/AXLvUQsrIv7s2CGM=\n"));
+        assert!(contents.starts_with(&pruned[..pruned.len() - 1]));
+    }
+
+    #[test]
+    fn parse_berry_lockfile() {
+        let contents = r#"# This file is generated by running "yarn install" inside your project.
+# Manual changes might be lost - proceed with caution!
+
+__metadata:
+  version: 6
+  cacheKey: 8
+
+"@fixture/app@workspace:packages/app":
+  version: 0.0.0-use.local
+  resolution: "@fixture/app@workspace:packages/app"
+  dependencies:
+    "@fixture/lib": ^1.0.0
+    react: ^17.0.0
+  languageName: unknown
+  linkType: soft
+
+"@fixture/lib@^1.0.0, @fixture/lib@workspace:packages/lib":
+  version: 0.0.0-use.local
+  resolution: "@fixture/lib@workspace:packages/lib"
+  languageName: unknown
+  linkType: soft
+
+"root@workspace:.":
+  version: 0.0.0-use.local
+  resolution: "root@workspace:."
+  languageName: unknown
+  linkType: soft
+
+"object-assign@npm:^4.1.1":
+  version: 4.1.1
+  resolution: "object-assign@npm:4.1.1"
+  checksum: fcc6e4ea8c7fe48abfbb552578b1c53e0d194086e2e6bbbf59e0a536381a292f39943c6e9628af05b5528aa5e3318bb30d6b2e53cadaf5b8fe9e12c4b69af23f
+  languageName: node
+  linkType: hard
+
+"react@npm:^17.0.0":
+  version: 17.0.2
+  resolution: "react@npm:17.0.2"
+  dependencies:
+    object-assign: ^4.1.1
+  checksum: b254cc17ce3011788330f7bbf383ab653c6848902d7936a87b09d835d091e3f295f7e9dd1597c6daac5dc80f90e778c8230218ba8ad599f74adcc11e33b9d61b
+  languageName: node
+  linkType: hard
+"#;
+        let lockfile = parse_berry(contents).unwrap();
+        // keyed like workspace paths, with the root as .
+        assert!(lockfile.importers["."].is_empty());
+        let app = &lockfile.importers["packages/app"];
+        assert_eq!(app.len(), 1);
+        let react = &lockfile.packages[app[&PackageName::new("react".to_owned()).unwrap()]];
+        assert_eq!(react.version, "17.0.2");
+        assert_eq!(
+            lockfile.packages[react.dependencies[0]].name.as_str(),
+            "object-assign"
+        );
+    }
+}
@@ -1,19 +1,41 @@
 use anyhow::Context;
+use filter::FilterOpts;
 pub(crate) use package_name::PackageName;
-use project::{Package, Project};
+use project::{Package, PackageKey, Project};
 use serde::Deserialize;
 use std::{
+    collections::BTreeSet,
     env,
     ffi::OsStr,
     fmt,
     path::{Path, PathBuf},
     process::{exit, Command},
+    str::FromStr,
 };
 use structopt::StructOpt;
 
+mod check;
+mod corepack;
+mod diff;
+mod discovery_cache;
+mod doctor;
+mod duplicates;
+mod filter;
+mod graph;
+mod lint;
+mod lockfile;
+mod overrides;
 mod package_json;
 mod package_name;
 mod project;
+mod range;
+mod run;
+#[cfg(test)]
+mod test_dir;
+mod version;
+mod why;
+mod workspace_globs;
+mod workspaces;
 
 #[derive(Deserialize)]
 struct RegistryMetadata {
@@ -84,7 +106,7 @@ async fn get_latest_version_of_react() {
     assert_eq!(result, "17.0.2")
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PackageManager {
     PNPM,
     NPM,
@@ -101,7 +123,25 @@ impl fmt::Display for PackageManager {
     }
 }
 
+impl FromStr for PackageManager {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pnpm" => Ok(PackageManager::PNPM),
+            "npm" => Ok(PackageManager::NPM),
+            "yarn" => Ok(PackageManager::Yarn),
+            _ => anyhow::bail!("\"{}\" is not one of npm, pnpm or yarn", s),
+        }
+    }
+}
+
 impl PackageManager {
+    /// In the order their lockfiles are looked for
+    const ALL: [PackageManager; 3] = [
+        PackageManager::PNPM,
+        PackageManager::Yarn,
+        PackageManager::NPM,
+    ];
     fn cmd(&self) -> Command {
         Command::new(match self {
             PackageManager::PNPM => "pnpm",
@@ -109,6 +149,13 @@ impl PackageManager {
             PackageManager::Yarn => "yarn",
         })
     }
+    fn lockfile_name(&self) -> &'static str {
+        match self {
+            PackageManager::PNPM => "pnpm-lock.yaml",
+            PackageManager::NPM => "package-lock.json",
+            PackageManager::Yarn => "yarn.lock",
+        }
+    }
 }
 
 fn run_package_manager_at_project_root<S: AsRef<OsStr> + std::fmt::Debug>(
@@ -116,8 +163,7 @@ fn run_package_manager_at_project_root<S: AsRef<OsStr> + std::fmt::Debug>(
     args: &[S],
 ) -> anyhow::Result<()> {
     let status = project
-        .manager
-        .cmd()
+        .manager_cmd()?
         .args(args)
         .current_dir(project.dir())
         .status()
@@ -155,14 +201,13 @@ fn run_script_or_binary(
     let bin = args[0].as_ref();
     let status = if pkg.scripts.contains_key(bin) {
         project
-            .manager
-            .cmd()
+            .manager_cmd()?
             .arg("run")
             .args(args)
             .status()
             .with_context(|| format!("Failed to run {} run with args {args:?}", project.manager))?
     } else {
-        let binary = find_binary_location(current_dir, &bin)?;
+        let binary = find_binary_location(current_dir, bin)?;
         Command::new(&binary)
             .args(&args[1..])
             .status()
@@ -189,16 +234,20 @@ enum Subcommand {
         /// Add to dev dependencies
         #[structopt(long, short)]
         dev: bool,
+        #[structopt(flatten)]
+        filter: FilterOpts,
     },
     /// Removes dependencies from the current package and runs install
     Remove {
         dependencies: Vec<PackageName>,
         /// Removes the package from everywhere in the project
-        #[structopt(long, short)]
+        #[structopt(long, short, conflicts_with_all = &["filters", "since"])]
         everywhere: bool,
         /// Skips the install step
         #[structopt(long, short)]
         skip_install: bool,
+        #[structopt(flatten)]
+        filter: FilterOpts,
     },
     /// Upgrades a dependency everywhere in the project and runs install
     Upgrade {
@@ -206,14 +255,118 @@ enum Subcommand {
         /// Skips the install step
         #[structopt(long, short)]
         skip_install: bool,
+        #[structopt(flatten)]
+        filter: FilterOpts,
+    },
+    /// Shows every path through which a package is depended on in the project
+    Why {
+        package: PackageName,
+        /// Prints the paths as JSON
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Lists the packages that are resolved to more than one version
+    Duplicates,
+    /// Checks that every dependency range in the project is satisfied by the lockfile
+    Check,
+    /// Checks the project for common problems
+    Lint(LintCommand),
+    /// Reports workspace packages with a package.json that can't be read
+    Doctor,
+    /// Bumps the version of packages and the ranges other workspace packages depend on them with
+    Version {
+        /// major, minor, patch or an exact version
+        bump: version::Bump,
+        /// Writes the changes without asking after showing them
+        #[structopt(long, short)]
+        yes: bool,
+        #[structopt(flatten)]
+        filter: FilterOpts,
+    },
+    /// Forces a version of a dependency everywhere in the dependency tree with the
+    /// overrides field of the package manager
+    Override {
+        /// The package and the version to force, like react@17.0.2
+        #[structopt(name = "package@range")]
+        descriptor: Option<String>,
+        /// Skips the install step
+        #[structopt(long, short)]
+        skip_install: bool,
+        #[structopt(subcommand)]
+        command: Option<OverrideCommand>,
+    },
+    /// Lists the packages in the project and how they depend on each other
+    Workspaces {
+        /// Shows the packages as a tree of their dependencies on each other
+        #[structopt(long)]
+        tree: bool,
+        /// Prints the packages as JSON
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Runs a script in the current package or in every workspace package
+    #[structopt(setting = structopt::clap::AppSettings::TrailingVarArg)]
+    Run {
+        /// The script and the arguments passed on to it. pyn's own flags go before the script
+        #[structopt(name = "script", required = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+        /// Runs the script in every workspace package that has it, after the packages they depend on.
+        /// Filtering the packages implies this
+        #[structopt(long, short)]
+        recursive: bool,
+        #[structopt(flatten)]
+        packages: run::PackagesOpts,
+        #[structopt(flatten)]
+        filter: FilterOpts,
+    },
+    /// Runs a command in every workspace package, after the packages they depend on
+    #[structopt(setting = structopt::clap::AppSettings::TrailingVarArg)]
+    Exec {
+        /// The command and its arguments, node_modules/.bin in the package and the root are on the PATH
+        #[structopt(required = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+        #[structopt(flatten)]
+        packages: run::PackagesOpts,
+        #[structopt(flatten)]
+        filter: FilterOpts,
     },
     #[structopt(external_subcommand)]
     Other(Vec<String>),
 }
 
+#[derive(StructOpt)]
+enum LintCommand {
+    /// Reports dependencies that are declared with different ranges across the project
+    Versions {
+        /// Aligns the ranges of each dependency
+        #[structopt(long)]
+        fix: bool,
+        /// The range to align to: highest, lowest or most-common. Defaults to
+        /// pyn.lint.versions.policy in the root package.json or highest
+        #[structopt(long)]
+        policy: Option<lint::Policy>,
+    },
+}
+
+#[derive(StructOpt)]
+enum OverrideCommand {
+    /// Lists the overrides in the root package.json
+    List,
+    /// Removes the overrides of a package and runs install
+    Remove {
+        package: PackageName,
+        /// Skips the install step
+        #[structopt(long, short)]
+        skip_install: bool,
+    },
+}
+
 #[derive(StructOpt)]
 #[structopt(about = "your nifty package manager runner")]
 struct Opts {
+    /// Uses this package manager (npm, pnpm or yarn) rather than detecting it
+    #[structopt(long, global = true)]
+    manager: Option<PackageManager>,
     #[structopt(subcommand)]
     subcommand: Option<Subcommand>,
 }
@@ -226,21 +379,22 @@ fn add_dep(pkg: &mut Package, dep: PackageName, version: String, dev: bool) {
     }
 }
 
+/// Adds the dependencies to each of the target packages
 fn add(
     project: &mut Project,
-    current_dir: &Path,
+    targets: &[PackageKey],
     dependencies: Vec<PackageName>,
     dev: bool,
 ) -> anyhow::Result<()> {
-    let mut pkg = project.closest_pkg(&current_dir).unwrap().clone();
+    let mut chosen = vec![];
 
     let deps_with_latests = get_latest_versions(dependencies)?;
 
     for (dep, latest_version) in deps_with_latests {
         let existing_versions = project.find_dependents(&dep);
         let latest_version_range = format!("^{}", latest_version);
-        if existing_versions.len() == 0 || existing_versions.get(&latest_version_range).is_some() {
-            add_dep(&mut pkg, dep, latest_version_range, dev);
+        if existing_versions.is_empty() || existing_versions.contains_key(&latest_version_range) {
+            chosen.push((dep, latest_version_range));
         } else {
             use dialoguer::{theme::ColorfulTheme, Select};
             if existing_versions.len() > 1 {
@@ -268,52 +422,88 @@ fn add(
                 0 => latest_version_range,
                 _ => items[selection].clone(),
             };
-            add_dep(&mut pkg, dep, version, dev);
+            chosen.push((dep, version));
+        }
+    }
+    for key in targets {
+        let pkg = project.get_mut(key).unwrap();
+        for (dep, version) in &chosen {
+            add_dep(pkg, dep.clone(), version.clone(), dev);
         }
+        // write the updated package.json back to disk
+        pkg.write()?;
     }
-    // write the updated package.json back to disk
-    pkg.write()?;
     Ok(())
 }
 
-fn upgrade(project: &mut Project, dependencies: Vec<PackageName>) -> anyhow::Result<()> {
+/// Upgrades the dependencies in every package, or only in the selected ones
+fn upgrade(
+    project: &mut Project,
+    dependencies: Vec<PackageName>,
+    selected: Option<&BTreeSet<PackageKey>>,
+) -> anyhow::Result<()> {
     let deps_with_latests = get_latest_versions(dependencies)?;
+    let mut upgraded = vec![];
 
     for (dep, latest_version) in deps_with_latests {
         let existing_versions = project.find_dependents(&dep);
         let latest_version = format!("^{}", latest_version);
 
-        if existing_versions.len() == 0 {
+        if existing_versions.is_empty() {
             println!(
                 "{} is not present in the repo. Did you mean to add it?",
                 &dep
             );
         } else {
-            for pkg in project.iter_mut() {
+            let targets = project
+                .iter_mut()
+                .filter(|pkg| selected.is_none_or(|selected| selected.contains(&pkg.key)));
+            for pkg in targets {
                 // upgrade the dependency
-                match pkg.pkg_json.set_dep_version(&dep, &latest_version) {
-                    Some(old_version) => {
-                        println!(
-                            "{} has been upgraded from {} to {} in {}",
-                            &dep, old_version, latest_version, pkg.pkg_json.name
-                        );
-                        // write the updated package.json back to disk
-                        pkg.write()?;
+                if let Some(old_version) = pkg.pkg_json.set_dep_version(&dep, &latest_version) {
+                    println!(
+                        "{} has been upgraded from {} to {} in {}",
+                        &dep, old_version, latest_version, pkg.key
+                    );
+                    // write the updated package.json back to disk
+                    pkg.write()?;
+                    if !upgraded.contains(&dep) {
+                        upgraded.push(dep.clone());
                     }
-                    None => {}
                 }
             }
         }
     }
+    // the old resolutions may still satisfy the new ranges so they have to go
+    // for the next install to actually pick up the new versions
+    if !upgraded.is_empty() {
+        let removed = lockfile::prune(project, &upgraded)?;
+        if removed != 0 {
+            println!(
+                "Removed {} entries from {}",
+                removed,
+                project.manager.lockfile_name()
+            );
+        }
+    }
     Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
-    let opt = Opts::from_args()
+    let opts = Opts::from_args();
+    let opt = opts
         .subcommand
         .unwrap_or_else(|| Subcommand::Other(vec!["install".to_owned()]));
     let current_dir = env::current_dir().unwrap();
-    let mut project = Project::find(&current_dir)?;
+    let mut project = Project::find(&current_dir, opts.manager)?;
+    if !matches!(opt, Subcommand::Doctor) {
+        if let Some(broken) = project.closest_broken(&current_dir) {
+            anyhow::bail!(
+                "The package.json of the current package is broken: {}",
+                broken
+            );
+        }
+    }
     match opt {
         Subcommand::Scripts => {
             let pkg_json = &project.closest_pkg(&current_dir).unwrap().pkg_json;
@@ -323,9 +513,14 @@ fn main() -> anyhow::Result<()> {
             dependencies,
             skip_install,
             dev,
+            filter,
         } => {
+            let targets = match filter.select(&project, &current_dir)? {
+                Some(selected) => selected.into_iter().collect(),
+                None => vec![project.closest_pkg(&current_dir).unwrap().key.clone()],
+            };
             // add the dependency
-            add(&mut project, &current_dir, dependencies, dev)?;
+            add(&mut project, &targets, dependencies, dev)?;
             // run install
             if !skip_install {
                 run_package_manager_at_project_root(&project, &["install"])?;
@@ -334,9 +529,12 @@ fn main() -> anyhow::Result<()> {
         Subcommand::Upgrade {
             dependencies,
             skip_install,
+            filter,
         } => {
+            project.ensure_all_loaded()?;
+            let selected = filter.select(&project, &current_dir)?;
             // add the dependency
-            upgrade(&mut project, dependencies)?;
+            upgrade(&mut project, dependencies, selected.as_ref())?;
             // run install
             if !skip_install {
                 run_package_manager_at_project_root(&project, &["install"])?;
@@ -346,7 +544,9 @@ fn main() -> anyhow::Result<()> {
             everywhere,
             dependencies,
             skip_install,
+            filter,
         } => {
+            let selected = filter.select(&project, &current_dir)?;
             println!("Removing {:?}", dependencies);
             let do_remove = |pkg: &mut Package| -> anyhow::Result<()> {
                 // remove the dependencies
@@ -357,7 +557,12 @@ fn main() -> anyhow::Result<()> {
                 pkg.write()?;
                 Ok(())
             };
-            if everywhere {
+            if let Some(selected) = selected {
+                for key in &selected {
+                    do_remove(project.get_mut(key).unwrap())?;
+                }
+            } else if everywhere {
+                project.ensure_all_loaded()?;
                 match &mut project.packages {
                     Some(_) => (),
                     None => {
@@ -381,10 +586,124 @@ fn main() -> anyhow::Result<()> {
                 run_package_manager_at_project_root(&project, &["install"])?;
             }
         }
-        Subcommand::Other(args) => match args.get(0).unwrap().as_str() {
-            "install" | "why" => run_package_manager_at_project_root(&project, &args),
+        // these report on the whole project so they'd be wrong without every package
+        Subcommand::Why { package, json } => {
+            project.ensure_all_loaded()?;
+            why::why(&project, &package, json)?
+        }
+        Subcommand::Duplicates => {
+            project.ensure_all_loaded()?;
+            duplicates::duplicates(&project)?
+        }
+        Subcommand::Check => {
+            project.ensure_all_loaded()?;
+            check::check(&project)?
+        }
+        Subcommand::Lint(LintCommand::Versions { fix, policy }) => {
+            project.ensure_all_loaded()?;
+            lint::lint_versions(&mut project, fix, policy)?
+        }
+        Subcommand::Doctor => doctor::doctor(&project)?,
+        Subcommand::Version { bump, yes, filter } => {
+            project.ensure_all_loaded()?;
+            let targets: Vec<PackageKey> = match filter.select(&project, &current_dir)? {
+                Some(selected) => selected.into_iter().collect(),
+                None => vec![project.closest_pkg(&current_dir).unwrap().key.clone()],
+            };
+            version::version(&mut project, &bump, &targets, yes)?
+        }
+        Subcommand::Override {
+            descriptor,
+            skip_install,
+            command,
+        } => {
+            let skip_install = match (descriptor, command) {
+                (Some(descriptor), None) => {
+                    overrides::add(&mut project, &descriptor)?;
+                    skip_install
+                }
+                (None, Some(OverrideCommand::List)) => {
+                    overrides::list(&project)?;
+                    true
+                }
+                (
+                    None,
+                    Some(OverrideCommand::Remove {
+                        package,
+                        skip_install,
+                    }),
+                ) => {
+                    overrides::remove(&mut project, &package)?;
+                    skip_install
+                }
+                _ => anyhow::bail!("Pass either <package>@<range>, list or remove <package>"),
+            };
+            if !skip_install {
+                run_package_manager_at_project_root(&project, &["install"])?;
+            }
+        }
+        Subcommand::Workspaces { tree, json } => workspaces::workspaces(&project, tree, json)?,
+        Subcommand::Run {
+            command,
+            recursive,
+            packages,
+            filter,
+        } => {
+            if recursive || !filter.is_empty() {
+                let (script, args) = command.split_first().unwrap();
+                project.ensure_all_loaded()?;
+                let selected = filter.select(&project, &current_dir)?;
+                run::run_recursive(&project, script, args, selected.as_ref(), &packages)?;
+            } else {
+                if !packages.is_empty() {
+                    anyhow::bail!(
+                        "--no-bail, --parallel and --concurrency only apply with --recursive or --filter"
+                    );
+                }
+                run_script_or_binary(&current_dir, &project, &command)?;
+            }
+        }
+        Subcommand::Exec {
+            command,
+            packages,
+            filter,
+        } => {
+            project.ensure_all_loaded()?;
+            let selected = filter.select(&project, &current_dir)?;
+            run::exec(&project, &command, selected.as_ref(), &packages)?;
+        }
+        Subcommand::Other(args) => match args.first().unwrap().as_str() {
+            "install" => run_package_manager_at_project_root(&project, &args),
             _ => run_script_or_binary(&current_dir, &project, &args),
         }?,
     }
     Ok(())
 }
+
+#[test]
+fn run_passes_flags_after_the_script_on() {
+    let parse = |args: &[&str]| match Opts::from_iter_safe(
+        std::iter::once("pyn").chain(args.iter().copied()),
+    )
+    .unwrap()
+    .subcommand
+    {
+        Some(Subcommand::Run {
+            command, recursive, ..
+        }) => (command, recursive),
+        _ => panic!("expected pyn run"),
+    };
+    assert_eq!(
+        parse(&["run", "build", "--watch"]),
+        (vec!["build".to_owned(), "--watch".to_owned()], false)
+    );
+    assert_eq!(
+        parse(&["run", "-r", "build", "--watch"]),
+        (vec!["build".to_owned(), "--watch".to_owned()], true)
+    );
+    // after the script -r is the script's
+    assert_eq!(
+        parse(&["run", "build", "-r"]),
+        (vec!["build".to_owned(), "-r".to_owned()], false)
+    );
+}
@@ -0,0 +1,419 @@
+use crate::{
+    lockfile::{split_descriptor, Lockfile},
+    package_json::{Override, PackageJson},
+    project::Project,
+    PackageManager, PackageName,
+};
+use anyhow::Context;
+use linked_hash_map::LinkedHashMap as InsertionOrderMap;
+
+/// The field of the root package.json the package manager reads overrides from
+fn field_name(manager: PackageManager) -> &'static str {
+    match manager {
+        PackageManager::NPM => "overrides",
+        PackageManager::Yarn => "resolutions",
+        PackageManager::PNPM => "pnpm.overrides",
+    }
+}
+
+/// The package an override applies to. Besides plain names, the keys can be Yarn
+/// paths like `**/react` or `@scope/a/react`, pnpm selectors like `a@1>react@<17`
+/// and npm keys with a version like `react@16`.
+fn target(key: &str) -> &str {
+    let key = key.rsplit('>').next().unwrap().trim();
+    let segments: Vec<&str> = key.split('/').collect();
+    let name_start = match segments.as_slice() {
+        [.., scope, _] if scope.starts_with('@') => segments.len() - 2,
+        _ => segments.len() - 1,
+    };
+    let name_start = segments[..name_start]
+        .iter()
+        .map(|segment| segment.len() + 1)
+        .sum();
+    let key = &key[name_start..];
+    split_descriptor(key).map_or(key, |(name, _)| name)
+}
+
+/// npm's nested overrides as `a > b`, where `.` is the version of the package they're nested in
+fn flatten(
+    prefix: Option<&str>,
+    overrides: &InsertionOrderMap<String, Override>,
+) -> Vec<(String, String)> {
+    let mut flattened = vec![];
+    for (key, value) in overrides {
+        let key = match (prefix, key.as_str()) {
+            (Some(prefix), ".") => prefix.to_owned(),
+            (Some(prefix), key) => format!("{} > {}", prefix, key),
+            (None, key) => key.to_owned(),
+        };
+        match value {
+            Override::Version(range) => flattened.push((key, range.clone())),
+            Override::Nested(nested) => flattened.extend(flatten(Some(&key), nested)),
+        }
+    }
+    flattened
+}
+
+/// Every override in the package.json as the key and the version it forces
+fn read_overrides(
+    pkg_json: &PackageJson,
+    manager: PackageManager,
+) -> anyhow::Result<Vec<(String, String)>> {
+    Ok(match manager {
+        PackageManager::NPM => flatten(None, &pkg_json.overrides()?.unwrap_or_default()),
+        PackageManager::Yarn => pkg_json
+            .resolutions()?
+            .unwrap_or_default()
+            .into_iter()
+            .collect(),
+        PackageManager::PNPM => pkg_json
+            .pnpm_overrides()?
+            .unwrap_or_default()
+            .into_iter()
+            .collect(),
+    })
+}
+
+/// Overrides the package everywhere, keeping the place of an existing override of it
+fn set_override(
+    pkg_json: &mut PackageJson,
+    manager: PackageManager,
+    name: &PackageName,
+    range: &str,
+) -> anyhow::Result<Option<String>> {
+    fn insert<V>(map: &mut InsertionOrderMap<String, V>, key: &str, value: V) -> Option<V> {
+        match map.get_mut(key) {
+            Some(existing) => Some(std::mem::replace(existing, value)),
+            None => map.insert(key.to_owned(), value),
+        }
+    }
+    let old = match manager {
+        PackageManager::NPM => {
+            let direct = pkg_json
+                .iter_normal_deps()
+                .any(|deps| deps.contains_key(name));
+            if direct && range != format!("${}", name) {
+                anyhow::bail!(
+                    "npm fails with EOVERRIDE when overriding {} since the root package.json depends on it, \
+                    change its range there or override it with ${} to use that range everywhere",
+                    name,
+                    name
+                );
+            }
+            let mut overrides = pkg_json.overrides()?.unwrap_or_default();
+            let old = match overrides.get_mut(name.as_str()) {
+                // the overrides nested in it apply to its dependencies so they stay
+                Some(Override::Nested(nested)) => {
+                    insert(nested, ".", Override::Version(range.to_owned()))
+                }
+                _ => insert(
+                    &mut overrides,
+                    name.as_str(),
+                    Override::Version(range.to_owned()),
+                ),
+            };
+            pkg_json.set_overrides(Some(overrides));
+            old.map(|old| match old {
+                Override::Version(version) => version,
+                Override::Nested(_) => "nested overrides".to_owned(),
+            })
+        }
+        PackageManager::Yarn => {
+            let mut resolutions = pkg_json.resolutions()?.unwrap_or_default();
+            let old = insert(&mut resolutions, name.as_str(), range.to_owned());
+            pkg_json.set_resolutions(Some(resolutions));
+            old
+        }
+        PackageManager::PNPM => {
+            let mut overrides = pkg_json.pnpm_overrides()?.unwrap_or_default();
+            let old = insert(&mut overrides, name.as_str(), range.to_owned());
+            pkg_json.set_pnpm_overrides(Some(overrides));
+            old
+        }
+    };
+    Ok(old)
+}
+
+/**
+Removes npm's overrides of the package at any depth, along with the maps left
+empty. Its own version within nested overrides is its `.`, so the overrides
+nested in it stay since they're for its dependencies.
+*/
+fn remove_nested(
+    prefix: Option<&str>,
+    overrides: &mut InsertionOrderMap<String, Override>,
+    name: &PackageName,
+) -> Vec<String> {
+    let mut removed = vec![];
+    let keys: Vec<String> = overrides.keys().cloned().collect();
+    for key in keys {
+        let path = match prefix {
+            Some(prefix) => format!("{} > {}", prefix, key),
+            None => key.clone(),
+        };
+        let targeted = target(&key) == name.as_str();
+        match overrides.get_mut(&key) {
+            Some(Override::Version(_)) if targeted => {
+                overrides.remove(&key);
+                removed.push(path);
+            }
+            Some(Override::Nested(nested)) => {
+                if targeted && nested.remove(".").is_some() {
+                    removed.push(path.clone());
+                }
+                removed.extend(remove_nested(Some(&path), nested, name));
+                if nested.is_empty() {
+                    overrides.remove(&key);
+                }
+            }
+            _ => {}
+        }
+    }
+    removed
+}
+
+/// Removes every override of the package, returning the keys that were removed
+fn remove_override(
+    pkg_json: &mut PackageJson,
+    manager: PackageManager,
+    name: &PackageName,
+) -> anyhow::Result<Vec<String>> {
+    fn remove_from<V>(map: &mut InsertionOrderMap<String, V>, name: &PackageName) -> Vec<String> {
+        let removed: Vec<String> = map
+            .keys()
+            .filter(|key| target(key) == name.as_str())
+            .cloned()
+            .collect();
+        for key in &removed {
+            map.remove(key);
+        }
+        removed
+    }
+    fn non_empty<V>(map: InsertionOrderMap<String, V>) -> Option<InsertionOrderMap<String, V>> {
+        Some(map).filter(|map| !map.is_empty())
+    }
+    Ok(match manager {
+        PackageManager::NPM => {
+            let mut overrides = pkg_json.overrides()?.unwrap_or_default();
+            let removed = remove_nested(None, &mut overrides, name);
+            pkg_json.set_overrides(non_empty(overrides));
+            removed
+        }
+        PackageManager::Yarn => {
+            let mut resolutions = pkg_json.resolutions()?.unwrap_or_default();
+            let removed = remove_from(&mut resolutions, name);
+            pkg_json.set_resolutions(non_empty(resolutions));
+            removed
+        }
+        PackageManager::PNPM => {
+            let mut overrides = pkg_json.pnpm_overrides()?.unwrap_or_default();
+            let removed = remove_from(&mut overrides, name);
+            pkg_json.set_pnpm_overrides(non_empty(overrides));
+            removed
+        }
+    })
+}
+
+fn in_lockfile(lockfile: &Lockfile, name: &str) -> bool {
+    lockfile
+        .packages
+        .iter()
+        .any(|pkg| pkg.name.as_str() == name)
+}
+
+/**
+Forces a version of a package everywhere in the dependency tree with the field
+the project's package manager reads, which is only worth it for a package that's
+actually installed, so it has to be in the lockfile.
+*/
+pub fn add(project: &mut Project, descriptor: &str) -> anyhow::Result<()> {
+    let (name, range) = split_descriptor(descriptor)
+        .filter(|(_, range)| !range.is_empty())
+        .with_context(|| format!("Expected <package>@<range> rather than \"{}\"", descriptor))?;
+    let name = PackageName::new(name.to_owned())?;
+    let lockfile = Lockfile::load(project)?;
+    if !in_lockfile(&lockfile, name.as_str()) {
+        anyhow::bail!(
+            "{} isn't in {} so overriding it wouldn't change anything",
+            name,
+            project.manager.lockfile_name()
+        );
+    }
+    let manager = project.manager;
+    let old = set_override(&mut project.root.pkg_json, manager, &name, range)?;
+    project.root.write()?;
+    match old {
+        Some(old) => println!(
+            "Changed the override of {} from {} to {} in {}",
+            name,
+            old,
+            range,
+            field_name(manager)
+        ),
+        None => println!(
+            "Overrode {} with {} in {}",
+            name,
+            range,
+            field_name(manager)
+        ),
+    }
+    Ok(())
+}
+
+pub fn list(project: &Project) -> anyhow::Result<()> {
+    let overrides = read_overrides(&project.root.pkg_json, project.manager)?;
+    if overrides.is_empty() {
+        println!(
+            "There are no {} in the root package.json",
+            field_name(project.manager)
+        );
+        return Ok(());
+    }
+    // the overrides can still be listed before the first install
+    let lockfile = Lockfile::load(project).ok();
+    for (key, range) in overrides {
+        let unused = lockfile
+            .as_ref()
+            .is_some_and(|lockfile| !in_lockfile(lockfile, target(&key)));
+        if unused {
+            println!(
+                "{}: {} (not in {})",
+                key,
+                range,
+                project.manager.lockfile_name()
+            );
+        } else {
+            println!("{}: {}", key, range);
+        }
+    }
+    Ok(())
+}
+
+pub fn remove(project: &mut Project, name: &PackageName) -> anyhow::Result<()> {
+    let manager = project.manager;
+    let removed = remove_override(&mut project.root.pkg_json, manager, name)?;
+    if removed.is_empty() {
+        anyhow::bail!(
+            "There are no overrides of {} in {}",
+            name,
+            field_name(manager)
+        );
+    }
+    project.root.write()?;
+    for key in removed {
+        println!("Removed {} from {}", key, field_name(manager));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_targets() {
+        assert_eq!(target("react"), "react");
+        assert_eq!(target("@types/react"), "@types/react");
+        assert_eq!(target("**/react"), "react");
+        assert_eq!(target("@scope/a/@types/react"), "@types/react");
+        assert_eq!(target("a@1>react@<17"), "react");
+        assert_eq!(target("@types/react@16"), "@types/react");
+        assert_eq!(target("a > react"), "react");
+        assert_eq!(target("a@1 > @types/react@16"), "@types/react");
+    }
+
+    #[test]
+    fn writes_the_managers_field() {
+        let react = PackageName::new("react".to_owned()).unwrap();
+        let contents = r#"{
+  "name": "root",
+  "overrides": {
+    "react": {
+      "scheduler": "0.20.0"
+    }
+  },
+  "pnpm": {
+    "neverBuiltDependencies": []
+  },
+  "license": "MIT"
+}
+"#;
+        let mut npm: PackageJson = contents.parse().unwrap();
+        set_override(&mut npm, PackageManager::NPM, &react, "17.0.2").unwrap();
+        assert_eq!(
+            read_overrides(&npm, PackageManager::NPM).unwrap(),
+            vec![
+                ("react > scheduler".to_owned(), "0.20.0".to_owned()),
+                ("react".to_owned(), "17.0.2".to_owned())
+            ]
+        );
+        assert_eq!(
+            remove_override(&mut npm, PackageManager::NPM, &react).unwrap(),
+            vec!["react"]
+        );
+        assert_eq!(npm.to_json_string().unwrap(), contents);
+
+        let mut yarn: PackageJson = contents.parse().unwrap();
+        set_override(&mut yarn, PackageManager::Yarn, &react, "17.0.2").unwrap();
+        assert!(yarn.to_json_string().unwrap().ends_with(
+            "  \"license\": \"MIT\",\n  \"resolutions\": {\n    \"react\": \"17.0.2\"\n  }\n}\n"
+        ));
+
+        let mut pnpm: PackageJson = contents.parse().unwrap();
+        set_override(&mut pnpm, PackageManager::PNPM, &react, "17.0.2").unwrap();
+        assert!(pnpm.to_json_string().unwrap().contains(
+            "  \"pnpm\": {\n    \"neverBuiltDependencies\": [],\n    \"overrides\": {\n      \"react\": \"17.0.2\"\n    }\n  },\n"
+        ));
+        remove_override(&mut pnpm, PackageManager::PNPM, &react).unwrap();
+        assert_eq!(pnpm.to_json_string().unwrap(), contents);
+    }
+
+    #[test]
+    fn removes_nested_npm_overrides() {
+        let scheduler = PackageName::new("scheduler".to_owned()).unwrap();
+        let mut pkg_json: PackageJson = r#"{
+  "overrides": {
+    "react-dom": {
+      "scheduler": "0.20.0"
+    },
+    "a@1": {
+      ".": "1.0.1",
+      "b": {
+        "scheduler": "0.19.0"
+      }
+    },
+    "scheduler": "0.20.2",
+    "c": "2.0.0"
+  }
+}"#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            remove_override(&mut pkg_json, PackageManager::NPM, &scheduler).unwrap(),
+            vec!["react-dom > scheduler", "a@1 > b > scheduler", "scheduler"]
+        );
+        assert_eq!(
+            read_overrides(&pkg_json, PackageManager::NPM).unwrap(),
+            vec![
+                ("a@1".to_owned(), "1.0.1".to_owned()),
+                ("c".to_owned(), "2.0.0".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn npm_overrides_of_root_dependencies() {
+        let react = PackageName::new("react".to_owned()).unwrap();
+        let mut pkg_json: PackageJson =
+            r#"{ "dependencies": { "react": "^17.0.0" } }"#.parse().unwrap();
+        assert!(set_override(&mut pkg_json, PackageManager::NPM, &react, "17.0.2").is_err());
+        assert_eq!(pkg_json.overrides().unwrap(), None);
+        set_override(&mut pkg_json, PackageManager::NPM, &react, "$react").unwrap();
+        assert_eq!(
+            read_overrides(&pkg_json, PackageManager::NPM).unwrap(),
+            vec![("react".to_owned(), "$react".to_owned())]
+        );
+        // Yarn doesn't mind
+        set_override(&mut pkg_json, PackageManager::Yarn, &react, "17.0.2").unwrap();
+    }
+}
@@ -1,12 +1,12 @@
-use crate::PackageName;
+use crate::{corepack::PinnedManager, PackageName};
 use anyhow::Context;
 use linked_hash_map::LinkedHashMap as InsertionOrderMap;
 use serde::{
-    de::{self, Visitor},
+    de::{self, DeserializeOwned, Visitor},
     ser::SerializeMap,
     Deserialize, Serialize, Serializer,
 };
-use serde_json::Value;
+use serde_json::{ser::PrettyFormatter, Value};
 use std::{collections::BTreeMap as KeyOrderedMap, path::Path, str::FromStr};
 
 type Dependencies = KeyOrderedMap<PackageName, String>;
@@ -17,15 +17,213 @@ enum PkgJsonValue {
     Value(Value),
 }
 
+/// How a package.json is laid out, so writing it back only changes the lines that were edited
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Format {
+    indent: String,
+    newline: String,
+    final_newline: bool,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format {
+            indent: "  ".to_owned(),
+            newline: "\n".to_owned(),
+            final_newline: true,
+        }
+    }
+}
+
+impl Format {
+    /**
+    Takes the indentation from the first indented line, falling back to two
+    spaces for files without one like `{}`, and uses CRLF if any line ends with it.
+    */
+    pub fn detect(contents: &str) -> Format {
+        let indent = contents
+            .lines()
+            .skip(1)
+            .map(|line| &line[..line.len() - line.trim_start_matches([' ', '\t']).len()])
+            .find(|indent| !indent.is_empty())
+            .unwrap_or("  ");
+        Format {
+            indent: indent.to_owned(),
+            newline: if contents.contains("\r\n") {
+                "\r\n"
+            } else {
+                "\n"
+            }
+            .to_owned(),
+            final_newline: contents.ends_with('\n'),
+        }
+    }
+}
+
+/// The `workspaces` field of npm and Yarn
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Workspaces {
+    Packages(Vec<String>),
+    /// Yarn's form, which can also list dependencies that shouldn't be hoisted
+    Nested {
+        packages: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        nohoist: Vec<String>,
+    },
+}
+
+impl Workspaces {
+    pub fn packages(&self) -> &[String] {
+        match self {
+            Workspaces::Packages(packages) | Workspaces::Nested { packages, .. } => packages,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Bin {
+    /// A single executable named after the package
+    Path(String),
+    Named(InsertionOrderMap<String, String>),
+}
+
+/// The entry points of a package, nested by the conditions they apply to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Exports {
+    Path(String),
+    /// The first of them that's supported is used
+    Fallbacks(Vec<Exports>),
+    /// Subpaths starting with `.` or conditions like `import` and `default`
+    Conditions(InsertionOrderMap<String, Exports>),
+    /// `null` hides a subpath
+    Excluded,
+}
+
+/// A version for npm's `overrides`, or overrides that only apply within a dependency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Override {
+    Version(String),
+    Nested(InsertionOrderMap<String, Override>),
+}
+
+/// The fields of an object in a package.json, in the order they're written in
+type ObjectFields = InsertionOrderMap<String, Value>;
+
+fn take_field<T: DeserializeOwned>(
+    fields: &mut ObjectFields,
+    key: &str,
+) -> serde_json::Result<Option<T>> {
+    fields.remove(key).map(serde_json::from_value).transpose()
+}
+
+/// Puts the known fields back with the others in the order they were read, with new ones last
+fn in_read_order(
+    order: &[String],
+    known: Vec<(&str, Option<Value>)>,
+    mut other: ObjectFields,
+) -> ObjectFields {
+    let mut known: InsertionOrderMap<&str, Value> = known
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect();
+    let mut fields = ObjectFields::new();
+    for key in order {
+        if let Some(value) = known.remove(key.as_str()).or_else(|| other.remove(key)) {
+            fields.insert(key.clone(), value);
+        }
+    }
+    fields.extend(
+        known
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value)),
+    );
+    fields.extend(other);
+    fields
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "ObjectFields", into = "ObjectFields")]
+pub struct PeerDependencyMeta {
+    pub optional: Option<bool>,
+    /// Fields pyn doesn't know about, which are written back as they were
+    pub other: ObjectFields,
+    order: Vec<String>,
+}
+
+impl TryFrom<ObjectFields> for PeerDependencyMeta {
+    type Error = serde_json::Error;
+    fn try_from(mut fields: ObjectFields) -> Result<Self, Self::Error> {
+        let order = fields.keys().cloned().collect();
+        Ok(PeerDependencyMeta {
+            optional: take_field(&mut fields, "optional")?,
+            other: fields,
+            order,
+        })
+    }
+}
+
+impl From<PeerDependencyMeta> for ObjectFields {
+    fn from(meta: PeerDependencyMeta) -> Self {
+        let known = vec![("optional", meta.optional.map(Value::Bool))];
+        in_read_order(&meta.order, known, meta.other)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "ObjectFields", into = "ObjectFields")]
+pub struct PublishConfig {
+    pub access: Option<String>,
+    pub registry: Option<String>,
+    pub tag: Option<String>,
+    pub directory: Option<String>,
+    /// Fields like `main` that replace the ones in the package.json when it's published
+    pub other: ObjectFields,
+    order: Vec<String>,
+}
+
+impl TryFrom<ObjectFields> for PublishConfig {
+    type Error = serde_json::Error;
+    fn try_from(mut fields: ObjectFields) -> Result<Self, Self::Error> {
+        let order = fields.keys().cloned().collect();
+        Ok(PublishConfig {
+            access: take_field(&mut fields, "access")?,
+            registry: take_field(&mut fields, "registry")?,
+            tag: take_field(&mut fields, "tag")?,
+            directory: take_field(&mut fields, "directory")?,
+            other: fields,
+            order,
+        })
+    }
+}
+
+impl From<PublishConfig> for ObjectFields {
+    fn from(config: PublishConfig) -> Self {
+        let known = vec![
+            ("access", config.access.map(Value::String)),
+            ("registry", config.registry.map(Value::String)),
+            ("tag", config.tag.map(Value::String)),
+            ("directory", config.directory.map(Value::String)),
+        ];
+        in_read_order(&config.order, known, config.other)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PackageJson {
-    pub name: PackageName,
+    /// Private packages like apps and the root of a workspace don't need one
+    pub name: Option<PackageName>,
     pub dependencies: Dependencies,
     pub dev_dependencies: Dependencies,
     pub optional_dependencies: Dependencies,
     pub peer_dependencies: Dependencies,
     pub scripts: InsertionOrderMap<String, String>,
     storage: InsertionOrderMap<String, PkgJsonValue>,
+    /// Detected when the package.json is parsed from a string
+    pub format: Format,
 }
 
 impl PackageJson {
@@ -51,28 +249,251 @@ impl PackageJson {
             deps.remove(pkg);
         }
     }
-    pub fn set_dep_version(&mut self, pkg: &PackageName, version: &String) -> Option<String> {
+    pub fn set_dep_version(&mut self, pkg: &PackageName, version: &str) -> Option<String> {
         let mut old_version = None;
         for deps in self.iter_mut_deps() {
             if let std::collections::btree_map::Entry::Occupied(mut entry) = deps.entry(pkg.clone())
             {
-                old_version = Some(entry.insert(version.clone()))
+                old_version = Some(entry.insert(version.to_owned()))
             }
         }
         old_version
     }
+    /// A field that isn't one of the fields above, as it is in the package.json
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self.storage.get(key) {
+            Some(PkgJsonValue::Value(value)) => Some(value),
+            _ => None,
+        }
+    }
+    /// Sets a field that isn't one of the fields above, keeping its place if it already exists
+    pub fn set(&mut self, key: &str, value: Value) {
+        match self.storage.get_mut(key) {
+            Some(existing) => *existing = PkgJsonValue::Value(value),
+            None => {
+                self.storage
+                    .insert(key.to_owned(), PkgJsonValue::Value(value));
+            }
+        }
+    }
+    /// Parses a package.json, keeping track of how it's formatted
+    pub fn from_json_str(s: &str) -> serde_json::Result<PackageJson> {
+        let mut pkg_json: PackageJson = serde_json::from_str(s)?;
+        pkg_json.format = Format::detect(s);
+        Ok(pkg_json)
+    }
+    /// The contents of the package.json as it will be written, formatted like it was read
+    pub fn to_json_string(&self) -> anyhow::Result<String> {
+        let mut bytes = vec![];
+        let formatter = PrettyFormatter::with_indent(self.format.indent.as_bytes());
+        self.serialize(&mut serde_json::Serializer::with_formatter(
+            &mut bytes, formatter,
+        ))?;
+        let mut stringified = String::from_utf8(bytes)?;
+        if self.format.newline != "\n" {
+            stringified = stringified.replace('\n', &self.format.newline);
+        }
+        if self.format.final_newline {
+            stringified.push_str(&self.format.newline);
+        }
+        Ok(stringified)
+    }
     pub fn write(&self, path: &Path) -> anyhow::Result<()> {
-        let mut stringified = serde_json::to_string_pretty(self)?;
-        stringified.push('\n');
+        let stringified = self.to_json_string()?;
         std::fs::write(path, stringified)
             .with_context(|| format!("Failed to write file at {}", path.display()))
     }
 }
 
+/**
+Typed access to the fields that aren't parsed up front. They're parsed when
+they're read so the ones nothing touches are written back exactly as they were,
+and setting one keeps its place in the file. A field with the wrong shape is an
+error when it's read, which `invalid_fields` collects for `pyn doctor`.
+*/
+impl PackageJson {
+    fn field<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
+        self.get(key)
+            .map(|value| T::deserialize(value))
+            .transpose()
+            .with_context(|| format!("{} in package.json is invalid", key))
+    }
+    fn set_field<T: Serialize>(&mut self, key: &str, value: Option<T>) {
+        match value {
+            Some(value) => self.set(
+                key,
+                serde_json::to_value(value).expect("package.json fields serialize to JSON"),
+            ),
+            None => {
+                self.storage.remove(key);
+            }
+        }
+    }
+    fn str_field(&self, key: &str) -> anyhow::Result<Option<&str>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => anyhow::bail!("{} in package.json isn't a string", key),
+        }
+    }
+
+    pub fn version(&self) -> anyhow::Result<Option<&str>> {
+        self.str_field("version")
+    }
+    pub fn set_version(&mut self, version: Option<&str>) {
+        self.set_field("version", version)
+    }
+    pub fn private(&self) -> anyhow::Result<bool> {
+        Ok(self.field("private")?.unwrap_or(false))
+    }
+    pub fn workspaces(&self) -> anyhow::Result<Option<Workspaces>> {
+        self.field("workspaces")
+    }
+    pub fn engines(&self) -> anyhow::Result<Option<InsertionOrderMap<String, String>>> {
+        self.field("engines")
+    }
+    pub fn bin(&self) -> anyhow::Result<Option<Bin>> {
+        self.field("bin")
+    }
+    pub fn main(&self) -> anyhow::Result<Option<&str>> {
+        self.str_field("main")
+    }
+    pub fn exports(&self) -> anyhow::Result<Option<Exports>> {
+        self.field("exports")
+    }
+    /// npm's overrides
+    pub fn overrides(&self) -> anyhow::Result<Option<InsertionOrderMap<String, Override>>> {
+        self.field("overrides")
+    }
+    pub fn set_overrides(&mut self, overrides: Option<InsertionOrderMap<String, Override>>) {
+        self.set_field("overrides", overrides)
+    }
+    /// Yarn's overrides
+    pub fn resolutions(&self) -> anyhow::Result<Option<InsertionOrderMap<String, String>>> {
+        self.field("resolutions")
+    }
+    pub fn set_resolutions(&mut self, resolutions: Option<InsertionOrderMap<String, String>>) {
+        self.set_field("resolutions", resolutions)
+    }
+    pub fn peer_dependencies_meta(
+        &self,
+    ) -> anyhow::Result<Option<InsertionOrderMap<PackageName, PeerDependencyMeta>>> {
+        self.field("peerDependenciesMeta")
+    }
+    pub fn publish_config(&self) -> anyhow::Result<Option<PublishConfig>> {
+        self.field("publishConfig")
+    }
+    /// pnpm's overrides, which live in the `pnpm` field
+    pub fn pnpm_overrides(&self) -> anyhow::Result<Option<InsertionOrderMap<String, String>>> {
+        self.get("pnpm")
+            .and_then(|pnpm| pnpm.get("overrides"))
+            .map(InsertionOrderMap::deserialize)
+            .transpose()
+            .context("pnpm.overrides in package.json is invalid")
+    }
+    /// Leaves the rest of the `pnpm` field alone and removes it if nothing's left
+    pub fn set_pnpm_overrides(&mut self, overrides: Option<InsertionOrderMap<String, String>>) {
+        let mut pnpm = match self.get("pnpm") {
+            Some(Value::Object(pnpm)) => pnpm.clone(),
+            _ => serde_json::Map::new(),
+        };
+        match overrides {
+            Some(overrides) => {
+                let overrides =
+                    serde_json::to_value(overrides).expect("package.json fields serialize to JSON");
+                match pnpm.get_mut("overrides") {
+                    Some(existing) => *existing = overrides,
+                    None => {
+                        pnpm.insert("overrides".to_owned(), overrides);
+                    }
+                }
+            }
+            // removing from the map directly would move its last field into the gap
+            None => {
+                pnpm = pnpm
+                    .into_iter()
+                    .filter(|(key, _)| key != "overrides")
+                    .collect()
+            }
+        }
+        self.set_field("pnpm", Some(pnpm).filter(|pnpm| !pnpm.is_empty()))
+    }
+    /// The package manager Corepack pins the project to
+    pub fn package_manager(&self) -> anyhow::Result<Option<PinnedManager>> {
+        self.str_field("packageManager")?
+            .map(|field| {
+                field
+                    .parse()
+                    .context("packageManager in package.json is invalid")
+            })
+            .transpose()
+    }
+    /// Why each typed field that's there can't be read
+    pub fn invalid_fields(&self) -> Vec<anyhow::Error> {
+        [
+            self.version().err(),
+            self.private().err(),
+            self.workspaces().err(),
+            self.engines().err(),
+            self.bin().err(),
+            self.main().err(),
+            self.exports().err(),
+            self.overrides().err(),
+            self.resolutions().err(),
+            self.peer_dependencies_meta().err(),
+            self.publish_config().err(),
+            self.pnpm_overrides().err(),
+            self.package_manager().err(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/**
+Setters for the fields no command writes yet. Each one has a round trip test, so
+the allow only applies outside of tests to catch a setter losing its test.
+*/
+#[cfg_attr(not(test), allow(dead_code))]
+impl PackageJson {
+    /// Removes the field rather than writing `false`
+    pub fn set_private(&mut self, private: bool) {
+        self.set_field("private", private.then_some(true))
+    }
+    pub fn set_workspaces(&mut self, workspaces: Option<Workspaces>) {
+        self.set_field("workspaces", workspaces)
+    }
+    pub fn set_engines(&mut self, engines: Option<InsertionOrderMap<String, String>>) {
+        self.set_field("engines", engines)
+    }
+    pub fn set_bin(&mut self, bin: Option<Bin>) {
+        self.set_field("bin", bin)
+    }
+    pub fn set_main(&mut self, main: Option<&str>) {
+        self.set_field("main", main)
+    }
+    pub fn set_exports(&mut self, exports: Option<Exports>) {
+        self.set_field("exports", exports)
+    }
+    pub fn set_peer_dependencies_meta(
+        &mut self,
+        meta: Option<InsertionOrderMap<PackageName, PeerDependencyMeta>>,
+    ) {
+        self.set_field("peerDependenciesMeta", meta)
+    }
+    pub fn set_publish_config(&mut self, publish_config: Option<PublishConfig>) {
+        self.set_field("publishConfig", publish_config)
+    }
+    pub fn set_package_manager(&mut self, pinned: Option<&PinnedManager>) {
+        self.set_field("packageManager", pinned)
+    }
+}
+
 impl FromStr for PackageJson {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(serde_json::from_str(s).context("Failed to deserialize package.json")?)
+        PackageJson::from_json_str(s).context("Failed to deserialize package.json")
     }
 }
 
@@ -84,7 +505,11 @@ impl Serialize for PackageJson {
                 state.serialize_entry(key, value)?;
             } else {
                 match key.as_str() {
-                    "name" => state.serialize_entry(key, &self.name)?,
+                    "name" => {
+                        if let Some(name) = &self.name {
+                            state.serialize_entry(key, name)?
+                        }
+                    }
                     "scripts" => {
                         if !self.scripts.is_empty() {
                             state.serialize_entry(key, &self.scripts)?
@@ -109,7 +534,7 @@ impl Serialize for PackageJson {
     }
 }
 
-const DEPENDENCY_TYPES: [&'static str; 4] = [
+const DEPENDENCY_TYPES: [&str; 4] = [
     "dependencies",
     "devDependencies",
     "peerDependencies",
@@ -172,16 +597,246 @@ impl<'de> Deserialize<'de> for PackageJson {
                     }
                 }
                 Ok(PackageJson {
-                    name: name.ok_or_else(|| de::Error::missing_field("name"))?,
+                    name,
                     scripts,
                     dependencies,
                     dev_dependencies,
                     optional_dependencies,
                     peer_dependencies,
                     storage,
+                    format: Format::default(),
                 })
             }
         }
         deserializer.deserialize_map(PackageJsonVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_formatting() {
+        let round_trip = |contents: &str| {
+            let mut pkg_json: PackageJson = contents.parse().unwrap();
+            let react = PackageName::new("react".to_owned()).unwrap();
+            pkg_json.dependencies.insert(react, "^17.0.0".to_owned());
+            pkg_json.to_json_string().unwrap()
+        };
+        assert_eq!(
+            round_trip("{\r\n\t\"name\": \"a\",\r\n\t\"dependencies\": {}\r\n}"),
+            "{\r\n\t\"name\": \"a\",\r\n\t\"dependencies\": {\r\n\t\t\"react\": \"^17.0.0\"\r\n\t}\r\n}"
+        );
+        assert_eq!(
+            round_trip("{\n    \"name\": \"a\"\n}\n"),
+            "{\n    \"name\": \"a\",\n    \"dependencies\": {\n        \"react\": \"^17.0.0\"\n    }\n}\n"
+        );
+        assert_eq!(
+            round_trip(r#"{ "name": "a" }"#),
+            "{\n  \"name\": \"a\",\n  \"dependencies\": {\n    \"react\": \"^17.0.0\"\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn typed_fields() {
+        let contents = r#"{
+  "name": "a",
+  "version": "1.0.0",
+  "private": true,
+  "bin": "cli.js",
+  "exports": {
+    ".": {
+      "import": "./index.mjs",
+      "default": "./index.js"
+    },
+    "./internal": null
+  },
+  "workspaces": {
+    "packages": [
+      "packages/*"
+    ],
+    "nohoist": [
+      "**/react"
+    ]
+  },
+  "resolutions": "not an object",
+  "license": "MIT"
+}
+"#;
+        let mut pkg_json: PackageJson = contents.parse().unwrap();
+        assert_eq!(pkg_json.version().unwrap(), Some("1.0.0"));
+        assert!(pkg_json.private().unwrap());
+        assert_eq!(
+            pkg_json.bin().unwrap(),
+            Some(Bin::Path("cli.js".to_owned()))
+        );
+        let exports = match pkg_json.exports().unwrap() {
+            Some(Exports::Conditions(exports)) => exports,
+            exports => panic!("unexpected exports {:?}", exports),
+        };
+        assert_eq!(exports["./internal"], Exports::Excluded);
+        assert_eq!(
+            pkg_json.workspaces().unwrap().unwrap().packages(),
+            ["packages/*"]
+        );
+        assert!(pkg_json.resolutions().is_err());
+        assert_eq!(pkg_json.overrides().unwrap(), None);
+        let invalid: Vec<String> = pkg_json
+            .invalid_fields()
+            .iter()
+            .map(|err| err.to_string())
+            .collect();
+        assert_eq!(invalid, ["resolutions in package.json is invalid"]);
+        // fields nothing set are written back as they were, even the invalid one
+        assert_eq!(pkg_json.to_json_string().unwrap(), contents);
+
+        pkg_json.set_version(Some("1.1.0"));
+        let mut resolutions = InsertionOrderMap::new();
+        resolutions.insert("react".to_owned(), "17.0.2".to_owned());
+        pkg_json.set_resolutions(Some(resolutions));
+        assert_eq!(
+            pkg_json.to_json_string().unwrap(),
+            contents
+                .replace("1.0.0", "1.1.0")
+                .replace("\"not an object\"", "{\n    \"react\": \"17.0.2\"\n  }")
+        );
+        pkg_json.set_version(None);
+        assert!(!pkg_json.to_json_string().unwrap().contains("version"));
+    }
+
+    #[test]
+    fn objects_keep_unknown_fields_in_order() {
+        let contents = r#"{
+  "version": 1,
+  "peerDependenciesMeta": {
+    "react": {
+      "futureFlag": "x",
+      "optional": true
+    }
+  },
+  "publishConfig": {
+    "main": "dist/index.js",
+    "access": "public",
+    "registry": "https://registry.example.com"
+  }
+}"#;
+        let mut pkg_json: PackageJson = contents.parse().unwrap();
+        assert_eq!(pkg_json.invalid_fields().len(), 1);
+        assert!(pkg_json.version().is_err());
+
+        let react = PackageName::new("react".to_owned()).unwrap();
+        let meta = pkg_json.peer_dependencies_meta().unwrap().unwrap();
+        assert_eq!(meta[&react].optional, Some(true));
+        assert_eq!(meta[&react].other["futureFlag"], "x");
+        pkg_json.set_peer_dependencies_meta(Some(meta));
+
+        let mut config = pkg_json.publish_config().unwrap().unwrap();
+        assert_eq!(config.access.as_deref(), Some("public"));
+        assert_eq!(config.other["main"], "dist/index.js");
+        config.access = Some("restricted".to_owned());
+        config.tag = Some("next".to_owned());
+        pkg_json.set_publish_config(Some(config));
+
+        assert_eq!(
+            pkg_json.to_json_string().unwrap(),
+            contents
+                .replace("public", "restricted")
+                .replace("example.com\"\n", "example.com\",\n    \"tag\": \"next\"\n")
+        );
+    }
+
+    #[test]
+    fn setters_round_trip() {
+        let contents = r#"{
+  "name": "a",
+  "private": true,
+  "main": "index.js",
+  "bin": {
+    "b": "cli.js",
+    "a": "other.js"
+  },
+  "exports": {
+    "./internal": null,
+    ".": [
+      {
+        "require": "./index.cjs"
+      },
+      "./index.js"
+    ]
+  },
+  "workspaces": {
+    "packages": [
+      "packages/*"
+    ],
+    "nohoist": [
+      "**/react"
+    ]
+  },
+  "engines": {
+    "node": ">=14",
+    "npm": ">=7"
+  },
+  "peerDependenciesMeta": {
+    "react": {
+      "optional": true
+    }
+  },
+  "publishConfig": {
+    "tag": "next",
+    "access": "public"
+  },
+  "packageManager": "pnpm@8.15.4+sha512.abc",
+  "license": "MIT"
+}
+"#;
+        let mut pkg_json: PackageJson = contents.parse().unwrap();
+        // writing back what was read changes nothing
+        pkg_json.set_private(pkg_json.private().unwrap());
+        pkg_json.set_main(pkg_json.main().unwrap().map(str::to_owned).as_deref());
+        pkg_json.set_bin(pkg_json.bin().unwrap());
+        pkg_json.set_exports(pkg_json.exports().unwrap());
+        pkg_json.set_workspaces(pkg_json.workspaces().unwrap());
+        pkg_json.set_engines(pkg_json.engines().unwrap());
+        pkg_json.set_peer_dependencies_meta(pkg_json.peer_dependencies_meta().unwrap());
+        pkg_json.set_publish_config(pkg_json.publish_config().unwrap());
+        pkg_json.set_package_manager(pkg_json.package_manager().unwrap().as_ref());
+        assert!(pkg_json.invalid_fields().is_empty());
+        assert_eq!(pkg_json.to_json_string().unwrap(), contents);
+
+        // edited fields keep their place, removed ones go
+        pkg_json.set_private(false);
+        pkg_json.set_main(None);
+        pkg_json.set_bin(Some(Bin::Path("cli.js".to_owned())));
+        pkg_json.set_exports(Some(Exports::Path("./index.js".to_owned())));
+        pkg_json.set_workspaces(Some(Workspaces::Packages(vec!["apps/*".to_owned()])));
+        let mut engines = pkg_json.engines().unwrap().unwrap();
+        *engines.get_mut("node").unwrap() = ">=18".to_owned();
+        pkg_json.set_engines(Some(engines));
+        pkg_json.set_peer_dependencies_meta(None);
+        pkg_json.set_publish_config(None);
+        let mut pinned = pkg_json.package_manager().unwrap().unwrap();
+        assert_eq!(pinned.manager, crate::PackageManager::PNPM);
+        pinned.version = semver::Version::new(9, 0, 0);
+        pinned.hash = None;
+        pkg_json.set_package_manager(Some(&pinned));
+        assert_eq!(
+            pkg_json.to_json_string().unwrap(),
+            r#"{
+  "name": "a",
+  "bin": "cli.js",
+  "exports": "./index.js",
+  "workspaces": [
+    "apps/*"
+  ],
+  "engines": {
+    "node": ">=18",
+    "npm": ">=7"
+  },
+  "packageManager": "pnpm@9.0.0",
+  "license": "MIT"
+}
+"#
+        );
+    }
+}
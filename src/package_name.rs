@@ -64,7 +64,7 @@ impl Eq for PackageName {}
 
 impl PartialOrd<PackageName> for PackageName {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.name.partial_cmp(&other.name)
+        Some(self.cmp(other))
     }
 }
 
@@ -75,10 +75,7 @@ impl Ord for PackageName {
 }
 
 fn is_valid_package_name_byte(byte: u8) -> bool {
-    match byte {
-        b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' => true,
-        _ => false,
-    }
+    matches!(byte, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.')
 }
 
 fn is_bytes_valid_pkg_name(bytes: &[u8]) -> bool {
@@ -88,7 +85,7 @@ fn is_bytes_valid_pkg_name(bytes: &[u8]) -> bool {
 impl TryFrom<String> for PackageName {
     type Error = PackageNameParseError;
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        PackageName::new(value.into())
+        PackageName::new(value)
     }
 }
 
@@ -106,9 +103,9 @@ impl FromStr for PackageName {
     }
 }
 
-impl Into<String> for PackageName {
-    fn into(self) -> String {
-        self.name
+impl From<PackageName> for String {
+    fn from(name: PackageName) -> String {
+        name.name
     }
 }
 
@@ -122,7 +119,7 @@ impl PackageName {
         self.name.as_ref()
     }
     pub fn new(name: String) -> Result<PackageName, PackageNameParseError> {
-        if name.len() > 214 || name.len() == 0 {
+        if name.len() > 214 || name.is_empty() {
             return Err(PackageNameParseError(name));
         }
         let bytes = name.as_bytes();
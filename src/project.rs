@@ -1,12 +1,22 @@
-use crate::{package_json::PackageJson, package_name::PackageName, PackageManager};
+use crate::{
+    corepack::{check_installed_version, PinnedManager},
+    discovery_cache,
+    package_json::PackageJson,
+    package_name::PackageName,
+    workspace_globs::WorkspaceGlobs,
+    PackageManager,
+};
 use anyhow::Context;
-use ignore::WalkBuilder;
-use serde::Deserialize;
+use ignore::{WalkBuilder, WalkState};
+use serde::{Deserialize, Serialize, Serializer};
 use std::{
-    collections::{BTreeMap, HashMap},
-    fs,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, fs,
     io::ErrorKind,
     path::{Path, PathBuf},
+    process::Command,
+    sync::{mpsc, Arc},
+    thread,
 };
 
 #[derive(Debug, Deserialize)]
@@ -14,44 +24,62 @@ struct PnpmWorkspaceConfig {
     packages: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
-enum NpmOrYarnWorkspaceConfig {
-    Packages(Vec<String>),
-    Nested { packages: Vec<String> },
+/**
+Identifies a package in the project by its name, or by its directory when it
+doesn't have one, which npm and pnpm allow for private packages like apps.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PackageKey {
+    Name(PackageName),
+    /// Relative to the root of the project, separated by `/`
+    Path(String),
 }
 
-#[derive(Debug, Deserialize)]
-struct PackageJsonForNpmOrYarnWorkspaceConfig {
-    workspaces: Option<NpmOrYarnWorkspaceConfig>,
+impl fmt::Display for PackageKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackageKey::Name(name) => write!(f, "{}", name),
+            PackageKey::Path(path) if path == "." => write!(f, "."),
+            PackageKey::Path(path) => write!(f, "./{}", path),
+        }
+    }
+}
+
+impl Serialize for PackageKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The directory relative to the root of the project, separated by `/` and `.` for the root itself
+pub fn relative_dir(root: &Path, dir: &Path) -> String {
+    let path = dir.strip_prefix(root).unwrap_or(dir);
+    if path.as_os_str().is_empty() {
+        ".".to_owned()
+    } else {
+        let parts: Vec<_> = path.iter().map(|part| part.to_string_lossy()).collect();
+        parts.join("/")
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Package {
     pub pkg_json_path: PathBuf,
     pub pkg_json: PackageJson,
+    pub key: PackageKey,
 }
 
 impl Package {
-    pub fn find<P: Into<PathBuf>>(path: P) -> std::io::Result<Package> {
-        let mut pkg_json_path = path.into();
-        pkg_json_path.push("package.json");
-        match std::fs::read_to_string(&pkg_json_path) {
-            Ok(contents) => Ok(Package {
-                pkg_json_path,
-                pkg_json: serde_json::from_str(&contents)?,
-            }),
-            Err(err) if err.kind() == ErrorKind::NotFound => {
-                if pkg_json_path.pop() {
-                    Package::find(pkg_json_path)
-                } else {
-                    Err(std::io::Error::new(
-                        ErrorKind::NotFound,
-                        "could not find a package.json",
-                    ))
-                }
-            }
-            Err(err) => Err(err.into()),
+    /// `root` is the directory of the project the package is in
+    pub fn new(root: &Path, pkg_json_path: PathBuf, pkg_json: PackageJson) -> Package {
+        let key = match &pkg_json.name {
+            Some(name) => PackageKey::Name(name.clone()),
+            None => PackageKey::Path(relative_dir(root, pkg_json_path.parent().unwrap())),
+        };
+        Package {
+            pkg_json_path,
+            pkg_json,
+            key,
         }
     }
     pub fn path(&self) -> &Path {
@@ -65,8 +93,12 @@ impl Package {
 #[derive(Debug, Clone)]
 pub struct Project {
     pub root: Package,
-    pub packages: Option<HashMap<PackageName, Package>>,
+    pub packages: Option<HashMap<PackageKey, Package>>,
     pub manager: PackageManager,
+    /// The version of the package manager pinned by the `packageManager` field
+    pub pinned_manager: Option<PinnedManager>,
+    /// The workspace packages that were left out because their package.json is broken
+    pub broken: Vec<BrokenManifest>,
 }
 
 /*
@@ -82,40 +114,50 @@ impl Project {
     pub fn dir(&self) -> &Path {
         self.root.path()
     }
+    pub fn lockfile_path(&self) -> PathBuf {
+        self.dir().join(self.manager.lockfile_name())
+    }
+    /// A command for the package manager, after checking it's the pinned version
+    pub fn manager_cmd(&self) -> anyhow::Result<Command> {
+        if let Some(pinned) = &self.pinned_manager {
+            check_installed_version(pinned)?;
+        }
+        Ok(self.manager.cmd())
+    }
     /**
     Finds all the usages of a dependency, returning the versions used, and the
-    names of the packages where each version is specified.
+    packages where each version is specified.
     */
     pub fn find_dependents(
         &self,
         name: &PackageName,
-    ) -> BTreeMap<VersionSpecifier, Vec<PackageName>> {
-        let mut matches: BTreeMap<VersionSpecifier, Vec<PackageName>> = Default::default();
+    ) -> BTreeMap<VersionSpecifier, Vec<PackageKey>> {
+        let mut matches: BTreeMap<VersionSpecifier, Vec<PackageKey>> = Default::default();
         for pkg in self.iter() {
             for deps in pkg.pkg_json.iter_normal_deps() {
                 if let Some(specifier) = deps.get(name) {
                     matches
                         .entry(specifier.clone())
                         .or_default()
-                        .push(pkg.pkg_json.name.clone())
+                        .push(pkg.key.clone())
                 }
             }
         }
 
         matches
     }
-    pub fn get_mut(&mut self, name: &PackageName) -> Option<&mut Package> {
-        if &self.root.pkg_json.name == name {
+    pub fn get_mut(&mut self, key: &PackageKey) -> Option<&mut Package> {
+        if &self.root.key == key {
             Some(&mut self.root)
         } else {
-            self.packages.as_mut().and_then(|map| map.get_mut(name))
+            self.packages.as_mut().and_then(|map| map.get_mut(key))
         }
     }
     pub fn closest_pkg(&self, mut path: &Path) -> Option<&Package> {
         let mut pkg_map = HashMap::new();
         pkg_map.insert(self.root.path(), &self.root);
         if let Some(pkgs) = &self.packages {
-            for (_, pkg) in pkgs {
+            for pkg in pkgs.values() {
                 pkg_map.insert(pkg.path(), pkg);
             }
         }
@@ -130,11 +172,40 @@ impl Project {
             }
         }
     }
+    /// The broken package.json of the package the path is in, when it's in one
+    pub fn closest_broken(&self, path: &Path) -> Option<&BrokenManifest> {
+        let closest = self.closest_pkg(path).map(|pkg| pkg.path());
+        self.broken
+            .iter()
+            .filter(|manifest| {
+                let dir = manifest.pkg_json_path.parent().unwrap();
+                path.starts_with(dir) && closest.is_none_or(|closest| dir.starts_with(closest))
+            })
+            .max_by_key(|manifest| manifest.pkg_json_path.components().count())
+    }
+    /**
+    Fails when some workspace packages were left out because of a broken
+    package.json, for commands that would do the wrong thing without them.
+    */
+    pub fn ensure_all_loaded(&self) -> anyhow::Result<()> {
+        if self.broken.is_empty() {
+            return Ok(());
+        }
+        let broken: Vec<_> = self
+            .broken
+            .iter()
+            .map(|manifest| manifest.to_string())
+            .collect();
+        anyhow::bail!(
+            "Fix the broken package.json files in the project first:\n  {}",
+            broken.join("\n  ")
+        )
+    }
     pub fn closest_pkg_mut(&mut self, path: &Path) -> Option<&mut Package> {
         match self.closest_pkg(path) {
             Some(pkg) => {
-                let name = pkg.pkg_json.name.clone();
-                self.get_mut(&name)
+                let key = pkg.key.clone();
+                self.get_mut(&key)
             }
             None => None,
         }
@@ -143,152 +214,834 @@ impl Project {
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Package> {
         self.packages
             .iter_mut()
-            .map(|map| map.iter_mut().map(|(_, pkg)| pkg))
-            .flatten()
+            .flat_map(|map| map.values_mut())
             .chain(std::iter::once(&mut self.root))
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &Package> {
         self.packages
             .iter()
-            .map(|map| map.iter().map(|(_, pkg)| pkg))
-            .flatten()
+            .flat_map(|map| map.values())
             .chain(std::iter::once(&self.root))
     }
 
-    pub fn find(path: &Path) -> anyhow::Result<Project> {
-        let dir =
-            fs::read_dir(path).with_context(|| format!("Failed to read dir {}", path.display()))?;
+    /**
+    Finds the project that contains the path, using `manager_override` as the
+    package manager rather than detecting it when it's given. The root is the
+    closest directory with a package.json and a lockfile, a `packageManager` or
+    `workspaces` field or a pnpm-workspace.yaml. Without any of those it's the
+    closest package.json, since that's what a project looks like before its
+    first install.
+    */
+    pub fn find(path: &Path, manager_override: Option<PackageManager>) -> anyhow::Result<Project> {
+        let mut found = None;
+        let mut closest = None;
+        for dir in path.ancestors() {
+            // checked in a fixed order so the result doesn't depend on the order of the directory entries
+            let lockfiles: Vec<PackageManager> = PackageManager::ALL
+                .into_iter()
+                .filter(|manager| dir.join(manager.lockfile_name()).exists())
+                .collect();
 
-        for entry in dir {
-            let entry =
-                entry.with_context(|| format!("Failed to read dir entry in {}", path.display()))?;
-            let package_manager = match entry.file_name().to_str() {
-                Some("yarn.lock") => PackageManager::Yarn,
-                Some("pnpm-lock.yaml") => PackageManager::PNPM,
-                Some("package-lock.json") => PackageManager::NPM,
-                _ => continue,
-            };
-            let pkg_json_path = path.join("package.json");
-
-            let pkg_json_string = fs::read_to_string(&pkg_json_path)
-                .with_context(|| format!("Failed to read file at {}", pkg_json_path.display()))?;
-
-            let package_globs: Option<Vec<String>> = match package_manager {
-                PackageManager::NPM | PackageManager::Yarn => {
-                    let pkg_json: PackageJsonForNpmOrYarnWorkspaceConfig =
-                        serde_json::from_str(&pkg_json_string).with_context(|| {
-                            format!(
-                                "Failed to deserialize package.json at {}",
-                                pkg_json_path.display()
-                            )
-                        })?;
-                    pkg_json.workspaces.map(|config| match config {
-                        NpmOrYarnWorkspaceConfig::Nested { packages }
-                        | NpmOrYarnWorkspaceConfig::Packages(packages) => packages,
+            let pkg_json_path = dir.join("package.json");
+            let pkg_json_string = match fs::read_to_string(&pkg_json_path) {
+                Ok(contents) => contents,
+                Err(_) if lockfiles.is_empty() => continue,
+                Err(err) => {
+                    return Err(err).with_context(|| {
+                        format!("Failed to read file at {}", pkg_json_path.display())
                     })
                 }
-                PackageManager::PNPM => {
-                    let pnpm_workspace_path = path.join("pnpm-workspace.yaml");
-                    match fs::read_to_string(&pnpm_workspace_path) {
-                        Ok(contents) => {
-                            let config: PnpmWorkspaceConfig = serde_yaml::from_str(&contents)?;
-                            config.packages
-                        }
-                        Err(err) if err.kind() == ErrorKind::NotFound => None,
-                        Err(err) => {
-                            return Err(err).context(format!(
-                                "Failed to read {}",
-                                pnpm_workspace_path.display()
-                            ))
-                        }
-                    }
+            };
+            let config = read_root_config(&pkg_json_path, &pkg_json_string, !lockfiles.is_empty())?;
+            let marker = if !lockfiles.is_empty() {
+                RootMarker::Lockfiles(lockfiles.clone())
+            } else if config.pinned_manager.is_some() {
+                RootMarker::PackageManagerField
+            } else if config.has_workspaces {
+                RootMarker::WorkspacesField
+            } else if dir.join("pnpm-workspace.yaml").exists() {
+                RootMarker::PnpmWorkspace
+            } else {
+                if closest.is_none() {
+                    closest = Some((dir, pkg_json_string, config));
                 }
+                continue;
             };
+            found = Some((dir, pkg_json_string, lockfiles, config, marker));
+            break;
+        }
+        let (path, pkg_json_string, lockfiles, config, marker) = match (found, closest) {
+            (Some(found), _) => found,
+            (None, Some((dir, pkg_json_string, config))) => (
+                dir,
+                pkg_json_string,
+                vec![],
+                config,
+                RootMarker::ClosestPackageJson,
+            ),
+            (None, None) => anyhow::bail!(
+                "Could not find a package.json in {} or any of its parent directories",
+                path.display()
+            ),
+        };
+        let pkg_json_path = path.join("package.json");
+        let pinned_manager = config.pinned_manager;
+
+        let package_manager = choose_manager(
+            path,
+            &lockfiles,
+            pinned_manager.as_ref().map(|pinned| pinned.manager),
+            manager_override,
+            if marker == RootMarker::PnpmWorkspace {
+                PackageManager::PNPM
+            } else {
+                PackageManager::NPM
+            },
+        )?;
+
+        let root = Package::new(path, pkg_json_path, pkg_json_string.parse()?);
+        let package_globs: Option<Vec<String>> = match package_manager {
+            PackageManager::NPM | PackageManager::Yarn => root
+                .pkg_json
+                .workspaces()
+                .with_context(|| format!("Failed to read {}", root.pkg_json_path.display()))?
+                .map(|workspaces| workspaces.packages().to_vec()),
+            PackageManager::PNPM => {
+                let pnpm_workspace_path = path.join("pnpm-workspace.yaml");
+                match fs::read_to_string(&pnpm_workspace_path) {
+                    Ok(contents) => {
+                        let config: PnpmWorkspaceConfig = serde_yaml::from_str(&contents)?;
+                        config.packages
+                    }
+                    Err(err) if err.kind() == ErrorKind::NotFound => None,
+                    Err(err) => {
+                        return Err(err)
+                            .context(format!("Failed to read {}", pnpm_workspace_path.display()))
+                    }
+                }
+            }
+        };
+
+        eprintln!(
+            "🧞 Found {} project at {} ({})",
+            package_manager,
+            path.display(),
+            marker
+        );
 
+        let (packages, broken) = match package_globs {
+            Some(globs) => {
+                let (packages, broken) =
+                    discovery_cache::discover_packages(&root, package_manager, &globs)?;
+                (Some(packages), broken)
+            }
+            None => (None, vec![]),
+        };
+        if !broken.is_empty() {
             eprintln!(
-                "🧞 Found {} project at {}",
-                package_manager,
-                pkg_json_path.parent().unwrap().display()
+                "⚠️  Skipping {} workspace packages with a broken package.json, run pyn doctor for details",
+                broken.len()
             );
+        }
+        Ok(Project {
+            manager: package_manager,
+            pinned_manager: pinned_manager.filter(|pinned| pinned.manager == package_manager),
+            packages,
+            root,
+            broken,
+        })
+    }
+}
 
-            return Ok(Project {
-                manager: package_manager,
-                packages: match package_globs {
-                    Some(globs) => {
-                        let packages_result: anyhow::Result<HashMap<PackageName, Package>> =
-                            find_packages(path, globs)
-                                .into_iter()
-                                .map(|path| -> anyhow::Result<(PackageName, Package)> {
-                                    let pkg = Package::find(path.parent().unwrap()).with_context(
-                                        || {
-                                            format!(
-                                                "Failed to load package with package.json at {}",
-                                                path.display()
-                                            )
-                                        },
-                                    )?;
-                                    let name = pkg.pkg_json.name.clone();
-                                    Ok((name, pkg))
-                                })
-                                .collect();
-                        Some(packages_result?)
-                    }
-                    None => None,
-                },
-                root: Package {
-                    pkg_json: pkg_json_string.parse()?,
-                    pkg_json_path,
-                },
-            });
+/// What made pyn pick a directory as the root of the project
+#[derive(Debug, PartialEq)]
+enum RootMarker {
+    Lockfiles(Vec<PackageManager>),
+    PackageManagerField,
+    WorkspacesField,
+    PnpmWorkspace,
+    ClosestPackageJson,
+}
+
+impl fmt::Display for RootMarker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RootMarker::Lockfiles(managers) => {
+                let names: Vec<_> = managers
+                    .iter()
+                    .map(|manager| manager.lockfile_name())
+                    .collect();
+                write!(f, "found {}", names.join(", "))
+            }
+            RootMarker::PackageManagerField => write!(f, "found packageManager in package.json"),
+            RootMarker::WorkspacesField => write!(f, "found workspaces in package.json"),
+            RootMarker::PnpmWorkspace => write!(f, "found pnpm-workspace.yaml"),
+            RootMarker::ClosestPackageJson => {
+                write!(f, "no lockfile yet so using the closest package.json")
+            }
+        }
+    }
+}
+
+struct RootConfig {
+    pinned_manager: Option<PinnedManager>,
+    has_workspaces: bool,
+}
+
+/**
+Reads the fields of a package.json that mark the root of a project, warning
+about and ignoring `packageManager` values pyn doesn't understand. When the
+directory has no lockfile the package.json may not belong to a project at all
+so it's allowed to be invalid.
+*/
+fn read_root_config(
+    pkg_json_path: &Path,
+    contents: &str,
+    has_lockfile: bool,
+) -> anyhow::Result<RootConfig> {
+    let pkg_json = match PackageJson::from_json_str(contents) {
+        Ok(pkg_json) => pkg_json,
+        Err(_) if !has_lockfile => {
+            return Ok(RootConfig {
+                pinned_manager: None,
+                has_workspaces: false,
+            })
+        }
+        Err(err) => {
+            return Err(err).with_context(|| {
+                format!(
+                    "Failed to deserialize package.json at {}",
+                    pkg_json_path.display()
+                )
+            })
         }
-        if let Some(parent_path) = path.parent() {
-            Project::find(parent_path)
+    };
+    let pinned_manager = match pkg_json.package_manager() {
+        Ok(pinned) => pinned,
+        Err(err) => {
+            eprintln!(
+                "⚠️  Ignoring the packageManager in {}: {:#}",
+                pkg_json_path.display(),
+                err
+            );
+            None
+        }
+    };
+    Ok(RootConfig {
+        pinned_manager,
+        // any shape marks the root, reading the globs reports a broken one
+        has_workspaces: pkg_json.get("workspaces").is_some(),
+    })
+}
+
+/**
+Picks the package manager of a project from the lockfiles in its root, the
+`packageManager` field in its package.json and the `--manager` flag. Conflicting
+signals get a warning, except for several lockfiles with nothing to choose
+between them, which is an error. Without any of them it's `without_lockfile`.
+*/
+fn choose_manager(
+    dir: &Path,
+    lockfiles: &[PackageManager],
+    declared: Option<PackageManager>,
+    manager_override: Option<PackageManager>,
+    without_lockfile: PackageManager,
+) -> anyhow::Result<PackageManager> {
+    let lockfile_names = |managers: &mut dyn Iterator<Item = &PackageManager>| {
+        managers
+            .map(|manager| manager.lockfile_name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let chosen = match (manager_override, declared) {
+        (Some(manager), declared) => {
+            if let Some(declared) = declared.filter(|&declared| declared != manager) {
+                eprintln!(
+                    "⚠️  Using {} because of --manager even though package.json declares {}",
+                    manager, declared
+                );
+            }
+            manager
+        }
+        (None, Some(manager)) => {
+            if !lockfiles.is_empty() && !lockfiles.contains(&manager) {
+                eprintln!(
+                    "⚠️  package.json declares {} as the package manager but there is no {} in {}",
+                    manager,
+                    manager.lockfile_name(),
+                    dir.display()
+                );
+            }
+            manager
+        }
+        (None, None) => match lockfiles {
+            [] => without_lockfile,
+            [manager] => *manager,
+            _ => anyhow::bail!(
+                "Found multiple lockfiles ({}) in {}, set the packageManager field in package.json \
+                 or pass --manager to choose between them",
+                lockfile_names(&mut lockfiles.iter()),
+                dir.display()
+            ),
+        },
+    };
+    let mut others = lockfiles
+        .iter()
+        .filter(|&&manager| manager != chosen)
+        .peekable();
+    if others.peek().is_some() {
+        eprintln!(
+            "⚠️  Using {} but {} is also in {}",
+            chosen,
+            lockfile_names(&mut others),
+            dir.display()
+        );
+    }
+    Ok(chosen)
+}
+
+/// A workspace package.json that couldn't be read or parsed
+#[derive(Debug, Clone)]
+pub struct BrokenManifest {
+    pub pkg_json_path: PathBuf,
+    /// The line and column of the problem when the JSON is invalid
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for BrokenManifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => write!(
+                f,
+                "{}:{}:{}: {}",
+                self.pkg_json_path.display(),
+                line,
+                column,
+                self.message
+            ),
+            None => write!(f, "{}: {}", self.pkg_json_path.display(), self.message),
+        }
+    }
+}
+
+/// Loads the package.json of a package in the project at `root`
+pub fn load_package(root: &Path, pkg_json_path: &Path) -> Result<Package, BrokenManifest> {
+    let broken = |location, message| BrokenManifest {
+        pkg_json_path: pkg_json_path.to_owned(),
+        location,
+        message,
+    };
+    let contents =
+        fs::read_to_string(pkg_json_path).map_err(|err| broken(None, err.to_string()))?;
+    match PackageJson::from_json_str(&contents) {
+        Ok(pkg_json) => Ok(Package::new(root, pkg_json_path.to_owned(), pkg_json)),
+        Err(err) => {
+            // serde_json puts the location at the end of the message
+            let message = err.to_string();
+            let suffix = format!(" at line {} column {}", err.line(), err.column());
+            let message = message.strip_suffix(&suffix).unwrap_or(&message).to_owned();
+            Err(broken(Some((err.line(), err.column())), message))
+        }
+    }
+}
+
+/**
+Parses the package.json files on a few threads, keeping them in order. The ones
+that can't be parsed are returned separately so one broken package doesn't stop
+pyn from working with the rest.
+*/
+pub fn parse_packages(
+    root: &Path,
+    pkg_json_paths: &[PathBuf],
+) -> (Vec<Package>, Vec<BrokenManifest>) {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = (pkg_json_paths.len() / threads).max(16);
+    let loaded: Vec<Result<Package, BrokenManifest>> = thread::scope(|scope| {
+        let handles: Vec<_> = pkg_json_paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|path| load_package(root, path))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    let mut packages = vec![];
+    let mut broken = vec![];
+    for result in loaded {
+        match result {
+            Ok(pkg) => packages.push(pkg),
+            Err(manifest) => broken.push(manifest),
+        }
+    }
+    (packages, broken)
+}
+
+/**
+Keys the workspace packages by name, or by path when they don't have one,
+failing when two of them (or one of them and the root) have the same name since
+edits to one would end up going to the other. The packages are checked in order
+so the error is the same every time.
+*/
+pub fn index_packages(
+    root: &Package,
+    loaded: Vec<Package>,
+) -> anyhow::Result<HashMap<PackageKey, Package>> {
+    let mut packages: HashMap<PackageKey, Package> = HashMap::new();
+    for pkg in loaded {
+        let name = &pkg.key;
+        let existing = if &root.key == name {
+            Some(root)
         } else {
-            anyhow::bail!("Could not find lockfile")
+            packages.get(name)
+        };
+        if let Some(existing) = existing {
+            anyhow::bail!(
+                "Found multiple packages named {}:\n  {}\n  {}",
+                name,
+                existing.pkg_json_path.display(),
+                pkg.pkg_json_path.display()
+            );
+        }
+        packages.insert(name.clone(), pkg);
+    }
+    Ok(packages)
+}
+
+/// What searching for the workspace packages found
+#[derive(Debug)]
+pub struct FoundPackages {
+    pub pkg_json_paths: Vec<PathBuf>,
+    /**
+    The directories where a new package would be found: every directory that
+    could hold one that was searched, and the directories the globs start from
+    even when they don't exist yet. Adding a package changes one of them.
+    */
+    pub dirs: Vec<PathBuf>,
+    /// The directories that couldn't be searched, as the package.json that couldn't be read
+    pub broken: Vec<BrokenManifest>,
+}
+
+enum Found {
+    PkgJson(PathBuf),
+    Dir(PathBuf),
+    Broken(BrokenManifest),
+}
+
+/// The path a walk error is about, looking through the context it's wrapped in
+fn error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
         }
+        _ => None,
     }
 }
 
-fn find_packages(root: &Path, globs: Vec<String>) -> Vec<PathBuf> {
-    let mut builder = ignore::overrides::OverrideBuilder::new(root);
-    for mut glob in globs {
-        glob.push_str("/package.json");
-        builder.add(&glob).unwrap();
-    }
-
-    let overrides_thing = builder.build().unwrap();
-    let mut package_json_paths = vec![];
-
-    for result in WalkBuilder::new(root)
-        .overrides(overrides_thing.clone())
-        .build()
-    {
-        let dir_entry = result.unwrap();
-        let path = dir_entry.path();
-        match overrides_thing.matched(path, false) {
-            ignore::Match::Whitelist(_) => package_json_paths.push(path.to_owned()),
-            _ => {}
+/**
+Like a package.json that can't be read, a directory that can't be searched only
+leaves out what's in it, so it's reported as a broken manifest where its
+package.json would be. Errors that aren't about a path stop the search.
+*/
+fn broken_walk_entry(err: &ignore::Error) -> Option<BrokenManifest> {
+    let path = error_path(err)?;
+    let pkg_json_path = if path.ends_with("package.json") {
+        path.to_owned()
+    } else {
+        path.join("package.json")
+    };
+    let message = match err.io_error() {
+        Some(io_err) => io_err.to_string(),
+        None => err.to_string(),
+    };
+    Some(BrokenManifest {
+        pkg_json_path,
+        location: None,
+        message,
+    })
+}
+
+/// Finds the package.json of every workspace package matched by the globs
+pub fn find_packages(
+    root: &Path,
+    manager: PackageManager,
+    globs: &[String],
+) -> anyhow::Result<FoundPackages> {
+    let globs = WorkspaceGlobs::new(manager, globs)?;
+    let max_depth = globs.max_depth();
+    let ignored_dirs = globs.ignored_dirs();
+
+    let globs = Arc::new(globs);
+    let root_dir = root.to_owned();
+    let filter_globs = globs.clone();
+
+    // package managers don't look at .gitignore when finding workspaces
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .hidden(true)
+        // the package.json of the deepest possible workspace is one level further down
+        .max_depth(globs.max_depth().map(|depth| depth + 1))
+        .filter_entry(move |entry| {
+            if !entry.file_type().is_some_and(|ty| ty.is_dir()) {
+                return true;
+            }
+            let ignored = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| ignored_dirs.contains(&name));
+            !ignored && filter_globs.could_contain(&relative_dir(&root_dir, entry.path()))
+        })
+        .build_parallel();
+    let (sender, receiver) = mpsc::channel();
+    walker.run(|| {
+        let sender = sender.clone();
+        let globs = &globs;
+        Box::new(move |result| {
+            let dir_entry = match result {
+                Ok(dir_entry) => dir_entry,
+                Err(err) => match broken_walk_entry(&err) {
+                    Some(manifest) => {
+                        sender.send(Ok(Found::Broken(manifest))).ok();
+                        return WalkState::Continue;
+                    }
+                    None => {
+                        sender.send(Err(err)).ok();
+                        return WalkState::Quit;
+                    }
+                },
+            };
+            let path = dir_entry.path();
+            let is_dir = dir_entry.file_type().is_some_and(|ty| ty.is_dir());
+            // the directories past the deepest workspace are only there to look for a package.json in
+            if is_dir
+                && dir_entry.depth() > 0
+                && max_depth.is_none_or(|max_depth| dir_entry.depth() <= max_depth)
+            {
+                sender.send(Ok(Found::Dir(path.to_owned()))).ok();
+            }
+            if dir_entry.file_name() != "package.json" {
+                return WalkState::Continue;
+            }
+            let dir = path.parent().unwrap().strip_prefix(root).unwrap();
+            if dir.as_os_str().is_empty() {
+                return WalkState::Continue;
+            }
+            let dir: Vec<_> = dir.iter().map(|part| part.to_string_lossy()).collect();
+            if globs.matches(&dir.join("/")) {
+                sender.send(Ok(Found::PkgJson(path.to_owned()))).ok();
+            }
+            WalkState::Continue
+        })
+    });
+    drop(sender);
+    let mut pkg_json_paths = vec![];
+    let mut broken = vec![];
+    let mut dirs: BTreeSet<PathBuf> = globs.bases().map(|base| root.join(base)).collect();
+    for found in receiver {
+        match found
+            .with_context(|| format!("Failed to search for packages in {}", root.display()))?
+        {
+            Found::PkgJson(path) => pkg_json_paths.push(path),
+            Found::Dir(dir) => {
+                dirs.insert(dir);
+            }
+            Found::Broken(manifest) => broken.push(manifest),
         }
     }
-    package_json_paths
+    pkg_json_paths.sort();
+    broken.sort_by(|a: &BrokenManifest, b| a.pkg_json_path.cmp(&b.pkg_json_path));
+    Ok(FoundPackages {
+        pkg_json_paths,
+        dirs: dirs.into_iter().collect(),
+        broken,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_dir::TestDir;
     use std::env;
     #[test]
     fn project_find() {
         let mut current_dir = env::current_dir().unwrap();
         current_dir.push("fixtures/basic");
-        dbg!(Project::find(&current_dir).unwrap());
+        dbg!(Project::find(&current_dir, None).unwrap());
     }
 
     #[test]
     fn find_packages_test() {
         let mut current_dir = env::current_dir().unwrap();
         current_dir.push("fixtures/basic");
-        dbg!(find_packages(&current_dir, vec!["packages/*".to_owned()]));
+        dbg!(find_packages(
+            &current_dir,
+            PackageManager::Yarn,
+            &["packages/*".to_owned()]
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn find_nested_packages() {
+        let root = TestDir::new();
+        for dir in [
+            "packages/a",
+            "packages/a/node_modules/dep",
+            "packages/group/b",
+            "packages/legacy/old",
+        ] {
+            root.write(format!("{}/package.json", dir), "{}");
+        }
+        fs::create_dir_all(root.join("packages/not-a-package/src")).unwrap();
+        let globs = ["packages/**".to_owned(), "!packages/legacy/**".to_owned()];
+        let found: Vec<_> = find_packages(root.path(), PackageManager::NPM, &globs)
+            .unwrap()
+            .pkg_json_paths
+            .into_iter()
+            .map(|path| path.strip_prefix(root.path()).unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                PathBuf::from("packages/a/package.json"),
+                PathBuf::from("packages/group/b/package.json"),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_dirs_outside_the_globs() {
+        let root = TestDir::new();
+        root.write(
+            "package.json",
+            r#"{ "name": "root", "private": true, "workspaces": ["packages/*/*"] }"#,
+        );
+        root.write("yarn.lock", "");
+        root.write("packages/group/a/package.json", r#"{ "name": "a" }"#);
+        root.write(
+            "packages/group/b/package.json",
+            r#"{ "name": "b", "dependencies": { "a": "^1.0.0" } }"#,
+        );
+        root.write("packages/group/a/node_modules/dep/package.json", "{}");
+        root.write("docs/site/package.json", r#"{ "name": "docs" }"#);
+        root.write("docs/site/pages/index.md", "");
+
+        let project = Project::find(root.path(), None).unwrap();
+        let mut names: Vec<String> = project
+            .packages
+            .unwrap()
+            .keys()
+            .map(|key| key.to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a", "b"]);
+
+        let found = find_packages(
+            root.path(),
+            PackageManager::Yarn,
+            &["packages/*/*".to_owned()],
+        )
+        .unwrap();
+        assert_eq!(found.pkg_json_paths.len(), 2);
+        // docs can't contain a workspace package so it isn't searched at all
+        assert!(found
+            .dirs
+            .iter()
+            .all(|dir| !dir.starts_with(root.join("docs"))));
+        assert!(found.dirs.contains(&root.join("packages/group")));
+    }
+
+    #[test]
+    fn duplicate_package_names() {
+        let root = TestDir::new();
+        for dir in ["packages/a", "packages/a-copy"] {
+            root.write(format!("{}/package.json", dir), r#"{ "name": "a" }"#);
+        }
+        root.write(
+            "package.json",
+            r#"{ "name": "root", "workspaces": ["packages/*"] }"#,
+        );
+        root.write("yarn.lock", "");
+        assert_eq!(
+            Project::find(root.path(), None).unwrap_err().to_string(),
+            format!(
+                "Found multiple packages named a:\n  {}\n  {}",
+                root.join("packages/a/package.json").display(),
+                root.join("packages/a-copy/package.json").display()
+            )
+        );
+    }
+
+    #[test]
+    fn broken_manifests() {
+        let root = TestDir::new();
+        root.write(
+            "package.json",
+            r#"{ "name": "root", "workspaces": ["packages/*"] }"#,
+        );
+        root.write("yarn.lock", "");
+        root.write("packages/a/package.json", r#"{ "name": "a" }"#);
+        root.write("packages/b/package.json", "{\n  \"name\": \"b\",\n}\n");
+        let project = Project::find(&root.join("packages/a"), None);
+
+        let project = project.unwrap();
+        assert_eq!(project.packages.as_ref().unwrap().len(), 1);
+        assert_eq!(project.broken.len(), 1);
+        let broken = &project.broken[0];
+        assert_eq!(broken.location, Some((3, 1)));
+        assert_eq!(broken.message, "trailing comma");
+        assert!(project.closest_broken(&root.join("packages/a")).is_none());
+        assert_eq!(
+            project
+                .closest_broken(&root.join("packages/b/src"))
+                .unwrap()
+                .pkg_json_path,
+            root.join("packages/b/package.json")
+        );
+        assert!(project.ensure_all_loaded().is_err());
+    }
+
+    #[test]
+    fn walk_errors() {
+        let unsearchable = ignore::Error::WithDepth {
+            depth: 2,
+            err: Box::new(ignore::Error::WithPath {
+                path: PathBuf::from("packages/secret"),
+                err: Box::new(ignore::Error::Io(std::io::Error::from_raw_os_error(13))),
+            }),
+        };
+        let broken = broken_walk_entry(&unsearchable).unwrap();
+        assert_eq!(
+            broken.pkg_json_path,
+            PathBuf::from("packages/secret/package.json")
+        );
+        assert_eq!(broken.location, None);
+        assert_eq!(broken.message, "Permission denied (os error 13)");
+
+        let loop_error = ignore::Error::Loop {
+            ancestor: PathBuf::from("packages"),
+            child: PathBuf::from("packages/link"),
+        };
+        assert_eq!(
+            broken_walk_entry(&loop_error).unwrap().pkg_json_path,
+            PathBuf::from("packages/link/package.json")
+        );
+        assert!(broken_walk_entry(&ignore::Error::Glob {
+            glob: None,
+            err: "bad glob".to_owned()
+        })
+        .is_none());
+    }
+
+    #[test]
+    #[ignore = "needs a non-root user"]
+    fn unsearchable_dirs() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = TestDir::new();
+        root.write(
+            "package.json",
+            r#"{ "name": "root", "workspaces": ["packages/**"] }"#,
+        );
+        root.write("yarn.lock", "");
+        root.write("packages/a/package.json", r#"{ "name": "a" }"#);
+        root.write("packages/secret/b/package.json", r#"{ "name": "b" }"#);
+        let secret = root.join("packages/secret");
+        fs::set_permissions(&secret, fs::Permissions::from_mode(0o000)).unwrap();
+        let project = Project::find(root.path(), None);
+        fs::set_permissions(&secret, fs::Permissions::from_mode(0o755)).unwrap();
+        let project = project.unwrap();
+        assert_eq!(project.packages.unwrap().len(), 1);
+        assert_eq!(project.broken.len(), 1);
+        assert_eq!(project.broken[0].pkg_json_path, secret.join("package.json"));
+    }
+
+    #[test]
+    fn unnamed_packages() {
+        let root = TestDir::new();
+        root.write(
+            "package.json",
+            r#"{ "private": true, "workspaces": ["apps/*", "packages/*"] }"#,
+        );
+        root.write("package-lock.json", "{}");
+        root.write(
+            "apps/web/package.json",
+            r#"{ "private": true, "dependencies": { "lib": "*" } }"#,
+        );
+        root.write("apps/docs/package.json", r#"{ "private": true }"#);
+        root.write("packages/lib/package.json", r#"{ "name": "lib" }"#);
+        let mut project = Project::find(&root.join("apps/web/src"), None).unwrap();
+        let web = project.closest_pkg_mut(&root.join("apps/web/src")).unwrap();
+        web.pkg_json.dependencies.clear();
+        web.write().unwrap();
+        let written = fs::read_to_string(root.join("apps/web/package.json"));
+
+        let mut keys: Vec<String> = project.iter().map(|pkg| pkg.key.to_string()).collect();
+        keys.sort();
+        assert_eq!(keys, vec![".", "./apps/docs", "./apps/web", "lib"]);
+        let docs = PackageKey::Path("apps/docs".to_owned());
+        assert!(project.get_mut(&docs).is_some());
+        assert_eq!(written.unwrap(), "{\n  \"private\": true\n}");
+    }
+
+    #[test]
+    fn choose_manager_test() {
+        use PackageManager::*;
+        let dir = Path::new("/repo");
+        assert_eq!(choose_manager(dir, &[Yarn], None, None, NPM).unwrap(), Yarn);
+        assert!(choose_manager(dir, &[Yarn, NPM], None, None, NPM).is_err());
+        assert_eq!(
+            choose_manager(dir, &[Yarn, NPM], Some(NPM), None, NPM).unwrap(),
+            NPM
+        );
+        assert_eq!(
+            choose_manager(dir, &[Yarn], Some(PNPM), None, NPM).unwrap(),
+            PNPM
+        );
+        assert_eq!(
+            choose_manager(dir, &[Yarn, NPM], Some(NPM), Some(Yarn), NPM).unwrap(),
+            Yarn
+        );
+        assert_eq!(choose_manager(dir, &[], None, None, PNPM).unwrap(), PNPM);
+    }
+
+    #[test]
+    fn root_without_lockfile() {
+        let root = TestDir::new();
+        root.write(
+            "npm/package.json",
+            r#"{ "name": "npm-root", "workspaces": ["packages/*"] }"#,
+        );
+        root.write("npm/packages/a/package.json", r#"{ "name": "a" }"#);
+        root.write("pnpm/package.json", r#"{ "name": "pnpm-root" }"#);
+        root.write("pnpm/pnpm-workspace.yaml", "packages:\n  - packages/*\n");
+        root.write("pnpm/packages/b/package.json", r#"{ "name": "b" }"#);
+        root.write("plain/package.json", r#"{ "name": "outer" }"#);
+        root.write("plain/inner/package.json", r#"{ "name": "inner" }"#);
+        let find = |dir: &str| Project::find(&root.join(dir), None);
+        let npm = find("npm/packages/a");
+        let pnpm = find("pnpm/packages/b/src");
+        let plain = find("plain/inner");
+
+        let npm = npm.unwrap();
+        assert_eq!(npm.manager, PackageManager::NPM);
+        assert_eq!(npm.root.key.to_string(), "npm-root");
+        assert_eq!(npm.packages.unwrap().len(), 1);
+        let pnpm = pnpm.unwrap();
+        assert_eq!(pnpm.manager, PackageManager::PNPM);
+        assert_eq!(pnpm.packages.unwrap().len(), 1);
+        let plain = plain.unwrap();
+        assert_eq!(plain.manager, PackageManager::NPM);
+        assert_eq!(plain.root.key.to_string(), "inner");
     }
 }
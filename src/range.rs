@@ -0,0 +1,433 @@
+use semver::{Prerelease, Version};
+use std::{
+    cmp::Ordering,
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+#[derive(Debug)]
+pub struct RangeParseError(String);
+
+impl std::error::Error for RangeParseError {}
+
+impl Display for RangeParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{}\" is not a valid semver range", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Eq,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Comparator {
+    op: Op,
+    version: Version,
+}
+
+impl Comparator {
+    fn new(op: Op, version: Version) -> Comparator {
+        Comparator { op, version }
+    }
+    fn matches(&self, version: &Version) -> bool {
+        let ordering = version.cmp(&self.version);
+        match self.op {
+            Op::Lt => ordering == Ordering::Less,
+            Op::Lte => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Gte => ordering != Ordering::Less,
+            Op::Eq => ordering == Ordering::Equal,
+        }
+    }
+}
+
+/**
+A range as npm understands it, e.g. `^1.2.0 || >=2.0.0 <3`. Everything gets
+desugared into sets of plain comparators where a version has to match every
+comparator in at least one of the sets.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    sets: Vec<Vec<Comparator>>,
+}
+
+/// A version where any of the parts can be missing or a wildcard, like `1.2` or `1.x`
+struct Partial {
+    major: Option<u64>,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: Prerelease,
+}
+
+fn version(major: u64, minor: u64, patch: u64) -> Version {
+    Version::new(major, minor, patch)
+}
+
+/// The lowest possible version below `major.minor.patch`, so that an exclusive
+/// upper bound also excludes the prereleases of that version.
+fn before(major: u64, minor: u64, patch: u64) -> Version {
+    Version {
+        pre: Prerelease::new("0").unwrap(),
+        ..version(major, minor, patch)
+    }
+}
+
+impl Partial {
+    fn parse(s: &str) -> Option<Partial> {
+        let s = s.trim_start_matches('=').trim_start_matches('v');
+        let s = s.split('+').next()?;
+        let (s, pre) = match s.split_once('-') {
+            Some((s, pre)) => (s, Prerelease::new(pre).ok()?),
+            None => (s, Prerelease::EMPTY),
+        };
+        let mut parts = s.split('.').map(|part| match part {
+            "x" | "X" | "*" => Ok(None),
+            _ => part.parse().map(Some),
+        });
+        let major = parts.next().unwrap_or(Ok(None)).ok()?;
+        let minor = parts
+            .next()
+            .unwrap_or(Ok(None))
+            .ok()?
+            .filter(|_| major.is_some());
+        let patch = parts
+            .next()
+            .unwrap_or(Ok(None))
+            .ok()?
+            .filter(|_| minor.is_some());
+        if parts.next().is_some() || (patch.is_none() && !pre.is_empty()) {
+            return None;
+        }
+        Some(Partial {
+            major,
+            minor,
+            patch,
+            pre,
+        })
+    }
+    fn full(&self) -> Option<Version> {
+        Some(Version {
+            pre: self.pre.clone(),
+            ..version(self.major?, self.minor?, self.patch?)
+        })
+    }
+    /// The lowest version the partial version covers
+    fn floor(&self) -> Version {
+        self.full().unwrap_or_else(|| {
+            version(
+                self.major.unwrap_or(0),
+                self.minor.unwrap_or(0),
+                self.patch.unwrap_or(0),
+            )
+        })
+    }
+    /// The lowest version above everything the partial version covers, if it isn't a full version
+    fn ceiling(&self) -> Option<Version> {
+        match (self.major, self.minor, self.patch) {
+            (Some(major), None, _) => Some(before(major + 1, 0, 0)),
+            (Some(major), Some(minor), None) => Some(before(major, minor + 1, 0)),
+            _ => None,
+        }
+    }
+}
+
+fn desugar(op: &str, partial: Partial) -> Vec<Comparator> {
+    use Op::*;
+    let major = match partial.major {
+        Some(major) => major,
+        // `<*` and `>*` can't match anything, the rest match everything
+        None if op == "<" || op == ">" => return vec![Comparator::new(Lt, before(0, 0, 0))],
+        None => return vec![],
+    };
+    let floor = partial.floor();
+    match (op, partial.full(), partial.ceiling()) {
+        ("" | "=", Some(full), _) => vec![Comparator::new(Eq, full)],
+        ("" | "=", None, Some(ceiling)) => {
+            vec![Comparator::new(Gte, floor), Comparator::new(Lt, ceiling)]
+        }
+        (">", Some(full), _) => vec![Comparator::new(Gt, full)],
+        (">", None, Some(ceiling)) => {
+            let ceiling = version(ceiling.major, ceiling.minor, ceiling.patch);
+            vec![Comparator::new(Gte, ceiling)]
+        }
+        (">=", _, _) => vec![Comparator::new(Gte, floor)],
+        ("<", Some(full), _) => vec![Comparator::new(Lt, full)],
+        ("<", None, _) => vec![Comparator::new(Lt, before(floor.major, floor.minor, 0))],
+        ("<=", Some(full), _) => vec![Comparator::new(Lte, full)],
+        ("<=", None, Some(ceiling)) => vec![Comparator::new(Lt, ceiling)],
+        ("~" | "~>", _, _) => {
+            let ceiling = match partial.minor {
+                Some(minor) => before(major, minor + 1, 0),
+                None => before(major + 1, 0, 0),
+            };
+            vec![Comparator::new(Gte, floor), Comparator::new(Lt, ceiling)]
+        }
+        ("^", _, _) => {
+            let ceiling = match (major, partial.minor, partial.patch) {
+                (0, None, _) => before(1, 0, 0),
+                (0, Some(0), None) => before(0, 1, 0),
+                (0, Some(0), Some(patch)) => before(0, 0, patch + 1),
+                (0, Some(minor), _) => before(0, minor + 1, 0),
+                _ => before(major + 1, 0, 0),
+            };
+            vec![Comparator::new(Gte, floor), Comparator::new(Lt, ceiling)]
+        }
+        _ => unreachable!("invalid operator {}", op),
+    }
+}
+
+fn split_operator(token: &str) -> (&str, &str) {
+    let end = token
+        .find(|c| !matches!(c, '<' | '>' | '=' | '~' | '^'))
+        .unwrap_or(token.len());
+    (&token[..end], &token[end..])
+}
+
+fn parse_comparator_set(s: &str) -> Option<Vec<Comparator>> {
+    if let Some((from, to)) = s.split_once(" - ") {
+        let (from, to) = (Partial::parse(from.trim())?, Partial::parse(to.trim())?);
+        let mut set = desugar(">=", from);
+        if to.major.is_some() {
+            set.extend(desugar("<=", to));
+        }
+        return Some(set);
+    }
+    let mut set = vec![];
+    let mut tokens = s.split_whitespace();
+    while let Some(token) = tokens.next() {
+        let (op, rest) = split_operator(token);
+        // operators can be separated from their version by whitespace
+        let rest = if rest.is_empty() && !op.is_empty() {
+            tokens.next()?
+        } else {
+            rest
+        };
+        if !matches!(op, "" | "=" | "<" | "<=" | ">" | ">=" | "~" | "~>" | "^") {
+            return None;
+        }
+        set.extend(desugar(op, Partial::parse(rest)?));
+    }
+    Some(set)
+}
+
+impl FromStr for Range {
+    type Err = RangeParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split("||")
+            .map(|set| parse_comparator_set(set.trim()))
+            .collect::<Option<Vec<_>>>()
+            .map(|sets| Range { sets })
+            .ok_or_else(|| RangeParseError(s.to_owned()))
+    }
+}
+
+/// One end of an interval of versions, `None` meaning unbounded
+#[derive(Debug, Clone)]
+struct Bound {
+    version: Version,
+    inclusive: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Interval {
+    lower: Option<Bound>,
+    upper: Option<Bound>,
+}
+
+impl Interval {
+    fn from_set(set: &[Comparator]) -> Option<Interval> {
+        let unbounded = Interval {
+            lower: None,
+            upper: None,
+        };
+        set.iter().try_fold(unbounded, |interval, comparator| {
+            let bound = |inclusive| {
+                Some(Bound {
+                    version: comparator.version.clone(),
+                    inclusive,
+                })
+            };
+            let (lower, upper) = match comparator.op {
+                Op::Lt => (None, bound(false)),
+                Op::Lte => (None, bound(true)),
+                Op::Gt => (bound(false), None),
+                Op::Gte => (bound(true), None),
+                Op::Eq => (bound(true), bound(true)),
+            };
+            interval.intersect(&Interval { lower, upper })
+        })
+    }
+    fn intersect(&self, other: &Interval) -> Option<Interval> {
+        let pick = |a: &Option<Bound>, b: &Option<Bound>, keep: Ordering| match (a, b) {
+            (Some(a), Some(b)) => Some(match a.version.cmp(&b.version) {
+                Ordering::Equal => Bound {
+                    version: a.version.clone(),
+                    inclusive: a.inclusive && b.inclusive,
+                },
+                ordering if ordering == keep => a.clone(),
+                _ => b.clone(),
+            }),
+            (bound, None) | (None, bound) => bound.clone(),
+        };
+        let interval = Interval {
+            lower: pick(&self.lower, &other.lower, Ordering::Greater),
+            upper: pick(&self.upper, &other.upper, Ordering::Less),
+        };
+        match (&interval.lower, &interval.upper) {
+            (Some(lower), Some(upper)) => match lower.version.cmp(&upper.version) {
+                Ordering::Greater => None,
+                Ordering::Equal if !(lower.inclusive && upper.inclusive) => None,
+                _ => Some(interval),
+            },
+            _ => Some(interval),
+        }
+    }
+}
+
+impl Range {
+    pub fn satisfies(&self, version: &Version) -> bool {
+        self.sets.iter().any(|set| {
+            set.iter().all(|comparator| comparator.matches(version))
+                // prereleases only match when the range opts into prereleases of the same version
+                && (version.pre.is_empty()
+                    || set.iter().any(|comparator| {
+                        !comparator.version.pre.is_empty()
+                            && (comparator.version.major, comparator.version.minor, comparator.version.patch)
+                                == (version.major, version.minor, version.patch)
+                    }))
+        })
+    }
+    fn intervals(&self) -> impl Iterator<Item = Interval> + '_ {
+        self.sets.iter().filter_map(|set| Interval::from_set(set))
+    }
+    /**
+    Orders ranges by the newest version they start at and then by how far up
+    they go, so `~17.0.1` is newer than `^17.0.0` which is newer than `~17.0.0`.
+    */
+    pub fn cmp_newest(&self, other: &Range) -> Ordering {
+        fn extent(range: &Range) -> (Option<Version>, Option<Option<Version>>) {
+            let lower = range
+                .intervals()
+                .filter_map(|interval| interval.lower.map(|bound| bound.version))
+                .max();
+            // an unbounded upper end goes the furthest so it's compared as None > Some
+            let upper = range
+                .intervals()
+                .map(|interval| interval.upper.map(|bound| bound.version))
+                .max_by(|a, b| match (a, b) {
+                    (None, None) => Ordering::Equal,
+                    (None, Some(_)) => Ordering::Greater,
+                    (Some(_), None) => Ordering::Less,
+                    (Some(a), Some(b)) => a.cmp(b),
+                });
+            (lower, upper)
+        }
+        let (lower, upper) = extent(self);
+        let (other_lower, other_upper) = extent(other);
+        lower
+            .cmp(&other_lower)
+            .then_with(|| match (upper, other_upper) {
+                (Some(None), Some(None)) | (None, None) => Ordering::Equal,
+                (Some(None), _) | (_, None) => Ordering::Greater,
+                (_, Some(None)) | (None, _) => Ordering::Less,
+                (Some(Some(a)), Some(Some(b))) => a.cmp(&b),
+            })
+    }
+}
+
+/// Whether there is any version that satisfies every one of the ranges.
+pub fn intersects(ranges: &[Range]) -> bool {
+    let mut intervals = vec![Interval {
+        lower: None,
+        upper: None,
+    }];
+    for range in ranges {
+        intervals = intervals
+            .iter()
+            .flat_map(|interval| {
+                range
+                    .intervals()
+                    .filter_map(move |other| interval.intersect(&other))
+            })
+            .collect();
+    }
+    !intervals.is_empty()
+}
+
+/**
+Splits a plain range of a single version like `^1.2.3`, `~1.2.3`, `>=1.2.3` or
+`1.2.3` into its operator and version, `None` for anything more involved.
+*/
+pub fn single_version(range: &str) -> Option<(&str, Version)> {
+    let operator = ["^", "~", ">=", "="]
+        .into_iter()
+        .find(|operator| range.starts_with(operator))
+        .unwrap_or("");
+    let version = Version::parse(&range[operator.len()..]).ok()?;
+    Some((operator, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn satisfies(range: &str, version: &str) -> bool {
+        range
+            .parse::<Range>()
+            .unwrap()
+            .satisfies(&Version::parse(version).unwrap())
+    }
+
+    #[test]
+    fn satisfies_npm_ranges() {
+        assert!(satisfies("^17.0.0", "17.0.2"));
+        assert!(!satisfies("^17.0.0", "18.0.0"));
+        assert!(satisfies("~17.0.1", "17.0.9"));
+        assert!(!satisfies("~17.0.1", "17.1.0"));
+        assert!(satisfies("^0.20.2", "0.20.9"));
+        assert!(!satisfies("^0.20.2", "0.21.0"));
+        assert!(!satisfies("^0.0.3", "0.0.4"));
+        assert!(satisfies("^3.0.0 || ^4.0.0", "4.0.0"));
+        assert!(satisfies("1.2.3 - 2.3", "2.3.9"));
+        assert!(!satisfies("1.2.3 - 2.3", "2.4.0"));
+        assert!(satisfies(">= 1.2 < 2", "1.9.0"));
+        assert!(satisfies("1.x", "1.5.0"));
+        assert!(satisfies("*", "5.0.0"));
+        assert!(satisfies("", "5.0.0"));
+        assert!(satisfies(">1.2", "1.3.0"));
+        assert!(!satisfies(">1.2", "1.2.9"));
+        assert!(!satisfies("^1.0.0", "1.1.0-beta.1"));
+        assert!(satisfies("^1.1.0-beta.0", "1.1.0-beta.1"));
+        assert!(!satisfies("^1.1.0-beta.0", "1.2.0-beta.1"));
+        assert!("latest".parse::<Range>().is_err());
+    }
+
+    #[test]
+    fn intersecting_ranges() {
+        let ranges = |ranges: &[&str]| -> Vec<Range> {
+            ranges.iter().map(|range| range.parse().unwrap()).collect()
+        };
+        assert!(intersects(&ranges(&["^17.0.0", "~17.0.1"])));
+        assert!(intersects(&ranges(&["^17.0.0", "17.0.2"])));
+        assert!(!intersects(&ranges(&["^16.0.0", "^17.0.0"])));
+        assert!(!intersects(&ranges(&[">1.0.0", "<=1.0.0"])));
+        assert!(intersects(&ranges(&["^16.0.0 || ^17.0.0", "^17.0.0"])));
+        assert!(!intersects(&ranges(&["~17.0.1", "~17.1.0"])));
+    }
+
+    #[test]
+    fn newest_ranges() {
+        let cmp = |a: &str, b: &str| a.parse::<Range>().unwrap().cmp_newest(&b.parse().unwrap());
+        assert_eq!(cmp("~17.0.1", "^17.0.0"), Ordering::Greater);
+        assert_eq!(cmp("^17.0.0", "~17.0.0"), Ordering::Greater);
+        assert_eq!(cmp(">=17.0.0", "^17.0.0"), Ordering::Greater);
+        assert_eq!(cmp("^16.0.0 || ^17.0.0", "^17.0.0"), Ordering::Equal);
+        assert_eq!(cmp("17.0.2", "^17.0.0"), Ordering::Greater);
+    }
+}
@@ -0,0 +1,435 @@
+use crate::{
+    graph::WorkspaceGraph,
+    project::{Package, PackageKey, Project},
+    PackageManager,
+};
+use anyhow::Context;
+use console::{Color, Style};
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    ffi::OsString,
+    fmt,
+    io::{BufRead, BufReader, Read},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+
+/// How to run something across several packages
+#[derive(Debug, StructOpt)]
+pub struct PackagesOpts {
+    /// Keeps running in the other packages when one fails
+    #[structopt(long)]
+    no_bail: bool,
+    /// Runs in as many packages at once as there are CPUs
+    #[structopt(long)]
+    parallel: bool,
+    /// Runs in up to this many packages at once
+    #[structopt(long)]
+    concurrency: Option<usize>,
+}
+
+impl PackagesOpts {
+    pub fn is_empty(&self) -> bool {
+        !self.no_bail && !self.parallel && self.concurrency.is_none()
+    }
+    fn concurrency(&self) -> usize {
+        match (self.parallel, self.concurrency) {
+            (_, Some(concurrency)) => concurrency.max(1),
+            (true, None) => thread::available_parallelism()
+                .map(|cpus| cpus.get())
+                .unwrap_or(1),
+            (false, None) => 1,
+        }
+    }
+}
+
+/**
+The packages to run something in, in the order to run them in, after the
+workspace packages they depend on. Unless the packages are selected, the root
+package is left out of projects with workspaces since its scripts are usually
+the ones running scripts recursively.
+*/
+fn target_packages<'a>(
+    project: &'a Project,
+    graph: &WorkspaceGraph<'a>,
+    selected: Option<&BTreeSet<PackageKey>>,
+) -> anyhow::Result<Vec<&'a Package>> {
+    Ok(graph
+        .topological_order()?
+        .into_iter()
+        .filter(|pkg| match selected {
+            Some(selected) => selected.contains(&pkg.key),
+            None => project.packages.is_none() || pkg.path() != project.dir(),
+        })
+        .collect())
+}
+
+/// The target packages that define the script
+fn packages_with_script<'a>(
+    project: &'a Project,
+    graph: &WorkspaceGraph<'a>,
+    script: &str,
+    selected: Option<&BTreeSet<PackageKey>>,
+) -> anyhow::Result<Vec<&'a Package>> {
+    Ok(target_packages(project, graph, selected)?
+        .into_iter()
+        .filter(|pkg| pkg.pkg_json.scripts.contains_key(script))
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Succeeded,
+    Failed,
+    /// Not run because something failed somewhere else first
+    Skipped,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Succeeded => write!(f, "succeeded"),
+            Status::Failed => write!(f, "failed"),
+            Status::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+/**
+For each package, the packages earlier in the list that it depends on, even
+through other packages, so it doesn't start until they've finished.
+*/
+fn waits_for(graph: &WorkspaceGraph, packages: &[&Package]) -> Vec<Vec<usize>> {
+    packages
+        .iter()
+        .map(|pkg| {
+            let deps = graph.transitive_dependencies(&pkg.key);
+            packages
+                .iter()
+                .enumerate()
+                .filter(|(_, other)| deps.iter().any(|dep| dep.path() == other.path()))
+                .map(|(idx, _)| idx)
+                .collect()
+        })
+        .collect()
+}
+
+const PREFIX_COLORS: [Color; 5] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Blue,
+    Color::Green,
+];
+
+/// Runs the command, printing every line of its output after the prefix when there is one
+fn run_command(mut cmd: Command, prefix: Option<(String, Color)>) -> std::io::Result<bool> {
+    let (label, color) = match prefix {
+        Some(prefix) => prefix,
+        None => return Ok(cmd.status()?.success()),
+    };
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let prefix_lines = |stream: Box<dyn Read + Send>, to_stderr: bool| {
+        let label = label.clone();
+        thread::spawn(move || {
+            let style = Style::new().fg(color);
+            for line in BufReader::new(stream).split(b'\n') {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                let line = String::from_utf8_lossy(&line);
+                if to_stderr {
+                    eprintln!("{} {}", style.clone().for_stderr().apply_to(&label), line);
+                } else {
+                    println!("{} {}", style.apply_to(&label), line);
+                }
+            }
+        })
+    };
+    let stdout = prefix_lines(Box::new(child.stdout.take().unwrap()), false);
+    let stderr = prefix_lines(Box::new(child.stderr.take().unwrap()), true);
+    let status = child.wait()?;
+    stdout.join().ok();
+    stderr.join().ok();
+    Ok(status.success())
+}
+
+fn summary(outcomes: &[(&str, Status, Option<Duration>)]) -> String {
+    let width = outcomes
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .chain(std::iter::once("Package".len()))
+        .max()
+        .unwrap();
+    let mut table = format!("{:width$}  {:9}  Duration\n", "Package", "Result");
+    for (name, status, duration) in outcomes {
+        let style = match status {
+            Status::Succeeded => Style::new().green(),
+            Status::Failed => Style::new().red(),
+            Status::Skipped => Style::new().dim(),
+        };
+        let duration = match duration {
+            Some(duration) => format!("{:.2}s", duration.as_secs_f64()),
+            None => "-".to_owned(),
+        };
+        table.push_str(&format!(
+            "{:width$}  {}  {}\n",
+            name,
+            style.apply_to(format!("{:9}", status.to_string())),
+            duration
+        ));
+    }
+    table
+}
+
+/**
+Runs a command in each of the packages, running up to `concurrency` packages at
+once. A package only starts once the packages it depends on have finished. When
+more than one package can run at once, each line of output is prefixed with the
+name of its package. Prints a summary at the end and fails if any package failed.
+*/
+fn run_in_packages(
+    graph: &WorkspaceGraph,
+    packages: &[&Package],
+    description: &str,
+    command: impl Fn(&Package) -> Command,
+    opts: &PackagesOpts,
+) -> anyhow::Result<()> {
+    let concurrency = opts.concurrency();
+    let bail = !opts.no_bail;
+    let waits_for = waits_for(graph, packages);
+    let labels: Vec<String> = packages.iter().map(|pkg| pkg.key.to_string()).collect();
+    let width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+
+    let mut started = vec![false; packages.len()];
+    let mut outcomes: Vec<Option<(Status, Duration)>> = vec![None; packages.len()];
+    let mut running = 0;
+    let mut stopped = false;
+    let (sender, receiver) = mpsc::channel();
+    loop {
+        while !stopped && running < concurrency {
+            let next = (0..packages.len()).find(|&idx| {
+                !started[idx] && waits_for[idx].iter().all(|&dep| outcomes[dep].is_some())
+            });
+            let idx = match next {
+                Some(idx) => idx,
+                None => break,
+            };
+            let pkg = packages[idx];
+            let name = &labels[idx];
+            let prefix = if concurrency > 1 {
+                Some((
+                    format!("{:width$} |", name),
+                    PREFIX_COLORS[idx % PREFIX_COLORS.len()],
+                ))
+            } else {
+                eprintln!("🧞 Running {} in {}", description, name);
+                None
+            };
+            let cmd = command(pkg);
+            let context = format!("Failed to run {} in {}", description, name);
+            let sender = sender.clone();
+            started[idx] = true;
+            running += 1;
+            thread::spawn(move || {
+                let start = Instant::now();
+                let succeeded = run_command(cmd, prefix)
+                    .with_context(|| context)
+                    .unwrap_or_else(|err| {
+                        eprintln!("{:?}", err);
+                        false
+                    });
+                sender.send((idx, succeeded, start.elapsed())).ok();
+            });
+        }
+        if running == 0 {
+            break;
+        }
+        let (idx, succeeded, duration) = receiver.recv()?;
+        running -= 1;
+        let status = if succeeded {
+            Status::Succeeded
+        } else {
+            stopped = bail;
+            Status::Failed
+        };
+        outcomes[idx] = Some((status, duration));
+    }
+
+    let outcomes: Vec<_> = labels
+        .iter()
+        .zip(outcomes)
+        .map(|(label, outcome)| match outcome {
+            Some((status, duration)) => (label.as_str(), status, Some(duration)),
+            None => (label.as_str(), Status::Skipped, None),
+        })
+        .collect();
+    print!("\n{}", summary(&outcomes));
+    let failed: Vec<_> = outcomes
+        .iter()
+        .filter(|(_, status, _)| *status == Status::Failed)
+        .map(|(name, _, _)| *name)
+        .collect();
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "{} failed in {} packages: {}",
+            description,
+            failed.len(),
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Runs the script in every workspace package that defines it
+pub fn run_recursive(
+    project: &Project,
+    script: &str,
+    args: &[String],
+    selected: Option<&BTreeSet<PackageKey>>,
+    opts: &PackagesOpts,
+) -> anyhow::Result<()> {
+    let graph = WorkspaceGraph::new(project);
+    let packages = packages_with_script(project, &graph, script, selected)?;
+    if packages.is_empty() {
+        if selected.is_some() {
+            eprintln!(
+                "🧞 None of the selected packages have a script named {}",
+                script
+            );
+            return Ok(());
+        }
+        anyhow::bail!("No packages have a script named {}", script);
+    }
+    // check the pinned version once rather than for every package
+    project.manager_cmd()?;
+    let command = |pkg: &Package| {
+        let mut cmd = project.manager.cmd();
+        cmd.arg("run").arg(script);
+        // npm takes arguments before the -- as its own
+        if project.manager == PackageManager::NPM && !args.is_empty() {
+            cmd.arg("--");
+        }
+        cmd.args(args).current_dir(pkg.path());
+        cmd
+    };
+    run_in_packages(&graph, &packages, script, command, opts)
+}
+
+/// PATH with the binaries installed in the package and then the root of the project in front of it
+fn path_with_bins(project: &Project, pkg: &Package) -> anyhow::Result<OsString> {
+    let mut paths = vec![pkg.path().join("node_modules/.bin")];
+    if pkg.path() != project.dir() {
+        paths.push(project.dir().join("node_modules/.bin"));
+    }
+    if let Some(path) = env::var_os("PATH") {
+        paths.extend(env::split_paths(&path));
+    }
+    env::join_paths(paths).context("Failed to add node_modules/.bin to PATH")
+}
+
+/// Runs a command in the directory of each workspace package
+pub fn exec(
+    project: &Project,
+    args: &[String],
+    selected: Option<&BTreeSet<PackageKey>>,
+    opts: &PackagesOpts,
+) -> anyhow::Result<()> {
+    let graph = WorkspaceGraph::new(project);
+    let packages = target_packages(project, &graph, selected)?;
+    if packages.is_empty() {
+        eprintln!("🧞 No packages are selected");
+        return Ok(());
+    }
+    let paths = packages
+        .iter()
+        .map(|pkg| Ok((pkg.path(), path_with_bins(project, pkg)?)))
+        .collect::<anyhow::Result<HashMap<_, _>>>()?;
+    let command = |pkg: &Package| {
+        let mut cmd = Command::new(&args[0]);
+        cmd.args(&args[1..])
+            .current_dir(pkg.path())
+            .env("PATH", &paths[pkg.path()]);
+        cmd
+    };
+    run_in_packages(&graph, &packages, &args[0], command, opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackageName;
+    use std::env;
+
+    #[test]
+    fn scripts_in_dependency_order() {
+        let mut dir = env::current_dir().unwrap();
+        dir.push("fixtures/yarn");
+        let mut project = Project::find(&dir, None).unwrap();
+        for pkg in project.iter_mut() {
+            pkg.pkg_json
+                .scripts
+                .insert("build".to_owned(), "tsc".to_owned());
+        }
+        let app = PackageKey::Name(PackageName::new("@fixture/app".to_owned()).unwrap());
+        let app = &mut project.get_mut(&app).unwrap().pkg_json;
+        app.scripts.insert("test".to_owned(), "jest".to_owned());
+
+        let graph = WorkspaceGraph::new(&project);
+        let names = |script| -> Vec<String> {
+            packages_with_script(&project, &graph, script, None)
+                .unwrap()
+                .into_iter()
+                .map(|pkg| pkg.key.to_string())
+                .collect()
+        };
+        assert_eq!(names("build"), vec!["@fixture/lib", "@fixture/app"]);
+        assert_eq!(names("test"), vec!["@fixture/app"]);
+        let packages = packages_with_script(&project, &graph, "build", None).unwrap();
+        assert_eq!(waits_for(&graph, &packages), vec![vec![], vec![0]]);
+    }
+
+    #[test]
+    fn bins_on_path() {
+        let mut dir = env::current_dir().unwrap();
+        dir.push("fixtures/npm");
+        let project = Project::find(&dir, None).unwrap();
+        let lib = PackageKey::Name(PackageName::new("@fixture/lib".to_owned()).unwrap());
+        let lib = project.iter().find(|pkg| pkg.key == lib).unwrap();
+        let path = path_with_bins(&project, lib).unwrap();
+        let paths: Vec<_> = env::split_paths(&path).take(2).collect();
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("packages/lib/node_modules/.bin"),
+                dir.join("node_modules/.bin")
+            ]
+        );
+    }
+
+    #[test]
+    fn summary_table() {
+        let table = summary(&[
+            (
+                "@fixture/lib",
+                Status::Succeeded,
+                Some(Duration::from_millis(1250)),
+            ),
+            ("@fixture/app", Status::Failed, Some(Duration::from_secs(3))),
+            ("docs", Status::Skipped, None),
+        ]);
+        // the statuses are colored when the tests run in a terminal
+        assert_eq!(
+            console::strip_ansi_codes(&table),
+            "Package       Result     Duration\n\
+             @fixture/lib  succeeded  1.25s\n\
+             @fixture/app  failed     3.00s\n\
+             docs          skipped    -\n"
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A directory to build a project in for a test, deleted when it's dropped even if the test fails
+pub struct TestDir(tempfile::TempDir);
+
+impl TestDir {
+    pub fn new() -> TestDir {
+        // a hidden directory would be skipped when walking it for packages
+        TestDir(
+            tempfile::Builder::new()
+                .prefix("pyn-")
+                .tempdir()
+                .expect("Failed to create a temporary directory"),
+        )
+    }
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.path().join(path)
+    }
+    /// Writes the file, creating the directories it's in
+    pub fn write(&self, path: impl AsRef<Path>, contents: &str) -> PathBuf {
+        let path = self.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{
+    diff,
+    project::{Package, PackageKey, Project},
+    range::{single_version, Range},
+};
+use anyhow::Context;
+use semver::{Prerelease, Version};
+use std::{collections::BTreeMap, fs, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bump {
+    Major,
+    Minor,
+    Patch,
+    Exact(Version),
+}
+
+impl FromStr for Bump {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "major" => Ok(Bump::Major),
+            "minor" => Ok(Bump::Minor),
+            "patch" => Ok(Bump::Patch),
+            _ => Ok(Bump::Exact(Version::parse(s).with_context(|| {
+                format!("\"{}\" is not one of major, minor, patch or a version", s)
+            })?)),
+        }
+    }
+}
+
+impl Bump {
+    /// Bumps the version the way `npm version` does, so bumping a prerelease releases it
+    fn apply(&self, version: &Version) -> Version {
+        let prerelease = !version.pre.is_empty();
+        match self {
+            Bump::Exact(version) => version.clone(),
+            Bump::Major if prerelease && version.minor == 0 && version.patch == 0 => {
+                Version::new(version.major, 0, 0)
+            }
+            Bump::Major => Version::new(version.major + 1, 0, 0),
+            Bump::Minor if prerelease && version.patch == 0 => {
+                Version::new(version.major, version.minor, 0)
+            }
+            Bump::Minor => Version::new(version.major, version.minor + 1, 0),
+            Bump::Patch if prerelease => Version {
+                pre: Prerelease::EMPTY,
+                ..version.clone()
+            },
+            Bump::Patch => Version::new(version.major, version.minor, version.patch + 1),
+        }
+    }
+}
+
+/**
+Points a range at the new version when it's a plain range of a single version
+like `^1.2.3`, `~1.2.3`, `>=1.2.3` or `1.2.3`, keeping the operator and any
+`workspace:` protocol. Anything more involved is left for a person to update.
+*/
+fn rewrite_range(range: &str, version: &Version) -> Option<String> {
+    let (protocol, range) = match range.strip_prefix("workspace:") {
+        Some(range) => ("workspace:", range),
+        None => ("", range),
+    };
+    let (operator, _) = single_version(range)?;
+    Some(format!("{}{}{}", protocol, operator, version))
+}
+
+/**
+Bumps the versions of the packages in memory and points the ranges other
+workspace packages depend on them with at the new versions. Peer dependency
+ranges are only changed when the new version is outside of them since widening
+them is up to the package. Returns the new version of each bumped package.
+*/
+fn plan(
+    project: &mut Project,
+    bump: &Bump,
+    targets: &[PackageKey],
+) -> anyhow::Result<BTreeMap<PackageKey, Version>> {
+    let mut versions = BTreeMap::new();
+    for name in targets {
+        let pkg = project.get_mut(name).unwrap();
+        let current = pkg
+            .pkg_json
+            .version()
+            .with_context(|| format!("{} has an invalid version", name))?
+            .with_context(|| format!("{} doesn't have a version", name))?;
+        let current =
+            Version::parse(current).with_context(|| format!("{} has an invalid version", name))?;
+        let version = bump.apply(&current);
+        pkg.pkg_json.set_version(Some(&version.to_string()));
+        versions.insert(name.clone(), version);
+    }
+    for pkg in project.iter_mut() {
+        let pkg_json = &mut pkg.pkg_json;
+        let normal_deps = [
+            &mut pkg_json.dependencies,
+            &mut pkg_json.dev_dependencies,
+            &mut pkg_json.optional_dependencies,
+        ]
+        .into_iter()
+        .map(|deps| (deps, false));
+        let peer_deps = std::iter::once((&mut pkg_json.peer_dependencies, true));
+        for (deps, is_peer) in normal_deps.chain(peer_deps) {
+            for (name, range) in deps.iter_mut() {
+                let version = match versions.get(&PackageKey::Name(name.clone())) {
+                    Some(version) => version,
+                    None => continue,
+                };
+                if is_peer
+                    && range
+                        .parse::<Range>()
+                        .map_or(true, |range| range.satisfies(version))
+                {
+                    continue;
+                }
+                if let Some(rewritten) = rewrite_range(range, version) {
+                    *range = rewritten;
+                }
+            }
+        }
+    }
+    Ok(versions)
+}
+
+/// The packages whose package.json would change if it was written, with the diff of each
+fn changes(project: &Project) -> anyhow::Result<Vec<(&Package, String)>> {
+    let mut changed = vec![];
+    for pkg in project.iter() {
+        let old = fs::read_to_string(&pkg.pkg_json_path)
+            .with_context(|| format!("Failed to read {}", pkg.pkg_json_path.display()))?;
+        let new = pkg.pkg_json.to_json_string()?;
+        if old != new {
+            changed.push((pkg, diff::unified(&old, &new)));
+        }
+    }
+    Ok(changed)
+}
+
+pub fn version(
+    project: &mut Project,
+    bump: &Bump,
+    targets: &[PackageKey],
+    yes: bool,
+) -> anyhow::Result<()> {
+    let versions = plan(project, bump, targets)?;
+    let changed = changes(project)?;
+    if changed.is_empty() {
+        println!("Nothing to bump, the packages are already at those versions");
+        return Ok(());
+    }
+    for (pkg, diff) in &changed {
+        println!("{}", pkg.pkg_json_path.display());
+        print!("{}", diff);
+    }
+    let confirmed = yes
+        || dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt(format!("Write the changes to {} files?", changed.len()))
+            .default(true)
+            .interact()?;
+    if !confirmed {
+        return Ok(());
+    }
+    for (pkg, _) in changed {
+        pkg.write()?;
+    }
+    for (name, version) in versions {
+        println!("{} is now at {}", name, version);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackageName;
+    use std::env;
+
+    #[test]
+    fn bumps() {
+        let bump = |bump: &str, version: &str| {
+            bump.parse::<Bump>()
+                .unwrap()
+                .apply(&Version::parse(version).unwrap())
+                .to_string()
+        };
+        assert_eq!(bump("major", "1.2.3"), "2.0.0");
+        assert_eq!(bump("minor", "1.2.3"), "1.3.0");
+        assert_eq!(bump("patch", "1.2.3"), "1.2.4");
+        assert_eq!(bump("major", "2.0.0-beta.1"), "2.0.0");
+        assert_eq!(bump("patch", "1.2.4-beta.1"), "1.2.4");
+        assert_eq!(bump("3.0.0-rc.0", "1.2.3"), "3.0.0-rc.0");
+        assert!("huge".parse::<Bump>().is_err());
+    }
+
+    #[test]
+    fn rewritten_ranges() {
+        let version = Version::new(1, 3, 0);
+        assert_eq!(rewrite_range("^1.2.0", &version).unwrap(), "^1.3.0");
+        assert_eq!(rewrite_range("~1.2.0", &version).unwrap(), "~1.3.0");
+        assert_eq!(rewrite_range("1.2.0", &version).unwrap(), "1.3.0");
+        assert_eq!(
+            rewrite_range("workspace:^1.2.0", &version).unwrap(),
+            "workspace:^1.3.0"
+        );
+        assert_eq!(rewrite_range("workspace:*", &version), None);
+        assert_eq!(rewrite_range("^1.0.0 || ^2.0.0", &version), None);
+    }
+
+    #[test]
+    fn bump_fixture() {
+        let mut dir = env::current_dir().unwrap();
+        dir.push("fixtures/pnpm");
+        let mut project = Project::find(&dir, None).unwrap();
+        let lib = PackageName::new("@fixture/lib".to_owned()).unwrap();
+        let app = PackageName::new("@fixture/app".to_owned()).unwrap();
+        let lib_key = PackageKey::Name(lib.clone());
+        let versions = plan(&mut project, &Bump::Major, std::slice::from_ref(&lib_key)).unwrap();
+        assert_eq!(versions[&lib_key], Version::new(2, 0, 0));
+        let app = &project.get_mut(&PackageKey::Name(app)).unwrap().pkg_json;
+        assert_eq!(app.dependencies[&lib], "^2.0.0");
+    }
+
+    #[test]
+    fn nothing_to_bump() {
+        let mut dir = env::current_dir().unwrap();
+        dir.push("fixtures/pnpm");
+        let mut project = Project::find(&dir, None).unwrap();
+        let lib = PackageKey::Name(PackageName::new("@fixture/lib".to_owned()).unwrap());
+        let same = Bump::Exact(Version::new(1, 0, 0));
+        plan(&mut project, &same, std::slice::from_ref(&lib)).unwrap();
+        assert!(changes(&project).unwrap().is_empty());
+        plan(&mut project, &Bump::Patch, &[lib]).unwrap();
+        assert_eq!(changes(&project).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{
+    lockfile::{importer_key, Lockfile},
+    project::{PackageKey, Project},
+    PackageName,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct DependencyPath {
+    /// The workspace package the path starts from
+    pub workspace: PackageKey,
+    /// Each package along the way as `name@version`, ending with the package itself
+    pub chain: Vec<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ResolvedVersion {
+    pub version: String,
+    pub paths: Vec<DependencyPath>,
+}
+
+/// Finds the shortest path from each workspace package to each resolved version of the package.
+pub fn find_paths(
+    project: &Project,
+    lockfile: &Lockfile,
+    name: &PackageName,
+) -> Vec<ResolvedVersion> {
+    let mut versions: BTreeMap<&str, Vec<DependencyPath>> = BTreeMap::new();
+    let mut workspaces: Vec<_> = project.iter().collect();
+    workspaces.sort_by_key(|pkg| importer_key(project, pkg));
+    for pkg in workspaces {
+        let importer = match lockfile.importer(project, pkg) {
+            Some(importer) => importer,
+            None => continue,
+        };
+        for path in lockfile.paths_to(importer, name) {
+            let target = &lockfile.packages[*path.last().unwrap()];
+            versions
+                .entry(&target.version)
+                .or_default()
+                .push(DependencyPath {
+                    workspace: pkg.key.clone(),
+                    chain: path
+                        .iter()
+                        .map(|&idx| {
+                            let pkg = &lockfile.packages[idx];
+                            format!("{}@{}", pkg.name, pkg.version)
+                        })
+                        .collect(),
+                });
+        }
+    }
+    versions
+        .into_iter()
+        .map(|(version, paths)| ResolvedVersion {
+            version: version.to_owned(),
+            paths,
+        })
+        .collect()
+}
+
+pub fn why(project: &Project, name: &PackageName, json: bool) -> anyhow::Result<()> {
+    let lockfile = Lockfile::load(project)?;
+    let versions = find_paths(project, &lockfile, name);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&versions)?);
+        return Ok(());
+    }
+    if versions.is_empty() {
+        println!("{} is not depended on anywhere in the project", name);
+    }
+    for resolved in versions {
+        println!("{}@{}", name, resolved.version);
+        for path in resolved.paths {
+            println!("  {} > {}", path.workspace, path.chain.join(" > "));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn react_paths(fixture: &str) -> Vec<(String, Vec<(String, String)>)> {
+        let mut dir = env::current_dir().unwrap();
+        dir.push("fixtures");
+        dir.push(fixture);
+        let project = Project::find(&dir, None).unwrap();
+        let lockfile = Lockfile::load(&project).unwrap();
+        let react = PackageName::new("react".to_owned()).unwrap();
+        find_paths(&project, &lockfile, &react)
+            .into_iter()
+            .map(|resolved| {
+                let paths = resolved
+                    .paths
+                    .into_iter()
+                    .map(|path| (path.workspace.to_string(), path.chain.join(" > ")))
+                    .collect();
+                (resolved.version, paths)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_paths_for_every_manager() {
+        let direct = vec![
+            (
+                "17.0.1".to_owned(),
+                vec![("@fixture/lib".to_owned(), "react@17.0.1".to_owned())],
+            ),
+            (
+                "17.0.2".to_owned(),
+                vec![("@fixture/app".to_owned(), "react@17.0.2".to_owned())],
+            ),
+        ];
+        assert_eq!(react_paths("yarn"), direct);
+        assert_eq!(react_paths("npm"), direct);
+        assert_eq!(react_paths("pnpm"), direct);
+    }
+}
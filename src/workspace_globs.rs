@@ -0,0 +1,175 @@
+use crate::PackageManager;
+use anyhow::Context;
+use globset::{GlobBuilder, GlobMatcher};
+
+/**
+The workspace globs of a project, matched against the path of each directory
+relative to the root the way the package manager does it. npm and Yarn apply
+the globs in order so a negated glob only excludes what the globs before it
+included, pnpm excludes anything matched by a negated glob wherever it is.
+*/
+#[derive(Debug)]
+pub struct WorkspaceGlobs {
+    globs: Vec<(bool, GlobMatcher)>,
+    ordered: bool,
+    max_depth: Option<usize>,
+    /// The directories the globs start from, like `packages` for `packages/*`
+    bases: Vec<Vec<String>>,
+    ignored_dirs: &'static [&'static str],
+}
+
+impl WorkspaceGlobs {
+    pub fn new(manager: PackageManager, globs: &[String]) -> anyhow::Result<WorkspaceGlobs> {
+        let globs = globs
+            .iter()
+            .map(|raw| {
+                let (negated, glob) = match raw.strip_prefix('!') {
+                    Some(glob) => (true, glob),
+                    None => (false, raw.as_str()),
+                };
+                let glob = glob.trim_start_matches("./").trim_end_matches('/');
+                let matcher = GlobBuilder::new(glob)
+                    .literal_separator(true)
+                    .build()
+                    .with_context(|| format!("Invalid workspace glob \"{}\"", raw))?
+                    .compile_matcher();
+                Ok((negated, matcher))
+            })
+            .collect::<anyhow::Result<Vec<(bool, GlobMatcher)>>>()?;
+        let max_depth = globs
+            .iter()
+            .filter(|(negated, _)| !negated)
+            .map(|(_, glob)| {
+                let glob = glob.glob().glob();
+                if glob.contains("**") {
+                    None
+                } else {
+                    Some(glob.split('/').count())
+                }
+            })
+            .try_fold(0, |max, depth| Some(max.max(depth?)));
+        let bases = globs
+            .iter()
+            .filter(|(negated, _)| !negated)
+            .map(|(_, glob)| {
+                glob.glob()
+                    .glob()
+                    .split('/')
+                    .take_while(|part| !part.contains(['*', '?', '[', '{']))
+                    .map(|part| part.to_owned())
+                    .collect()
+            })
+            .collect();
+        Ok(WorkspaceGlobs {
+            globs,
+            max_depth,
+            bases,
+            ordered: manager != PackageManager::PNPM,
+            ignored_dirs: match manager {
+                PackageManager::PNPM => &["node_modules", "bower_components"],
+                PackageManager::NPM | PackageManager::Yarn => &["node_modules"],
+            },
+        })
+    }
+
+    /// The names of directories that are never searched for workspace packages
+    pub fn ignored_dirs(&self) -> &'static [&'static str] {
+        self.ignored_dirs
+    }
+
+    /// How many directories deep a workspace can be, `None` when a glob can match any depth
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    /// The directories the globs start from, relative to the root and separated by `/`
+    pub fn bases(&self) -> impl Iterator<Item = String> + '_ {
+        self.bases.iter().map(|base| base.join("/"))
+    }
+
+    /**
+    Whether there could be workspaces in the directory at the relative path,
+    which is only the case on the way to or inside the directory a glob starts
+    from, so the rest of the project doesn't have to be searched.
+    */
+    pub fn could_contain(&self, dir: &str) -> bool {
+        let parts: Vec<&str> = dir
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .collect();
+        self.bases.iter().any(|base| {
+            parts
+                .iter()
+                .zip(base)
+                .all(|(part, base_part)| part == base_part)
+        })
+    }
+
+    /// Whether the directory at the relative path, separated by `/`, is a workspace
+    pub fn matches(&self, dir: &str) -> bool {
+        let mut included = false;
+        for (negated, glob) in &self.globs {
+            if glob.is_match(dir) {
+                if *negated && !self.ordered {
+                    return false;
+                }
+                included = !negated;
+            }
+        }
+        included
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn globs(manager: PackageManager, globs: &[&str]) -> WorkspaceGlobs {
+        let globs: Vec<String> = globs.iter().map(|&glob| glob.to_owned()).collect();
+        WorkspaceGlobs::new(manager, &globs).unwrap()
+    }
+
+    #[test]
+    fn nested_globs() {
+        let star = globs(PackageManager::Yarn, &["packages/*"]);
+        assert!(star.matches("packages/a"));
+        assert!(!star.matches("packages/a/b"));
+        let globstar = globs(PackageManager::Yarn, &["./packages/**/"]);
+        assert!(globstar.matches("packages/a"));
+        assert!(globstar.matches("packages/a/b"));
+        assert!(!globstar.matches("other/a"));
+        assert_eq!(star.max_depth(), Some(2));
+        assert_eq!(globstar.max_depth(), None);
+        let both = globs(
+            PackageManager::PNPM,
+            &["apps/*", "packages/*/*", "!**/test"],
+        );
+        assert_eq!(both.max_depth(), Some(3));
+        assert!(both.could_contain("."));
+        assert!(both.could_contain("packages"));
+        assert!(both.could_contain("packages/group/a"));
+        assert!(!both.could_contain("docs"));
+        let anywhere = globs(PackageManager::PNPM, &["*", "!docs"]);
+        assert!(anywhere.could_contain("docs/a"));
+    }
+
+    #[test]
+    fn negation_order() {
+        let patterns = ["packages/**", "!packages/legacy/**", "packages/legacy/kept"];
+        let npm = globs(PackageManager::NPM, &patterns);
+        assert!(npm.matches("packages/a"));
+        assert!(!npm.matches("packages/legacy/old"));
+        assert!(npm.matches("packages/legacy/kept"));
+        let pnpm = globs(PackageManager::PNPM, &patterns);
+        assert!(pnpm.matches("packages/a"));
+        assert!(!pnpm.matches("packages/legacy/old"));
+        assert!(!pnpm.matches("packages/legacy/kept"));
+    }
+
+    #[test]
+    fn invalid_glob() {
+        let err =
+            WorkspaceGlobs::new(PackageManager::NPM, &["packages/[a".to_owned()]).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid workspace glob \"packages/[a\"");
+    }
+}
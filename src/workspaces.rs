@@ -0,0 +1,173 @@
+use crate::{
+    graph::WorkspaceGraph,
+    project::{relative_dir, PackageKey, Project},
+    PackageName,
+};
+use serde::Serialize;
+use std::collections::HashSet;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Workspace {
+    #[serde(skip)]
+    pub key: PackageKey,
+    pub name: Option<PackageName>,
+    pub version: Option<String>,
+    /// Relative to the root of the project, separated by `/`
+    pub path: String,
+    pub private: bool,
+    /// The other workspace packages this one depends on
+    pub dependencies: Vec<PackageKey>,
+}
+
+/// Describes every package in the project, including the root, ordered by name and then path
+pub fn find_workspaces(project: &Project, graph: &WorkspaceGraph) -> Vec<Workspace> {
+    let mut packages: Vec<_> = project.iter().collect();
+    packages.sort_by(|a, b| a.key.cmp(&b.key));
+    packages
+        .into_iter()
+        .map(|pkg| {
+            let pkg_json = &pkg.pkg_json;
+            Workspace {
+                key: pkg.key.clone(),
+                name: pkg_json.name.clone(),
+                // `pyn doctor` reports fields with the wrong shape
+                version: pkg_json
+                    .version()
+                    .ok()
+                    .flatten()
+                    .map(|version| version.to_owned()),
+                path: relative_dir(project.dir(), pkg.path()),
+                private: pkg_json.private().unwrap_or(false),
+                dependencies: graph
+                    .dependencies(&pkg.key)
+                    .into_iter()
+                    .map(|dep| dep.key.clone())
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/**
+Draws the packages that nothing else depends on with the workspace packages
+they depend on beneath them. A package's dependencies are only drawn the first
+time it appears.
+*/
+fn tree(graph: &WorkspaceGraph, workspaces: &[Workspace]) -> String {
+    fn draw(
+        graph: &WorkspaceGraph,
+        key: &PackageKey,
+        indent: &str,
+        drawn: &mut HashSet<PackageKey>,
+        out: &mut String,
+    ) {
+        let deps = graph.dependencies(key);
+        for (idx, dep) in deps.iter().enumerate() {
+            let last = idx == deps.len() - 1;
+            let dep_key = &dep.key;
+            let expand = drawn.insert(dep_key.clone());
+            out.push_str(&format!(
+                "{}{}{}{}\n",
+                indent,
+                if last { "└── " } else { "├── " },
+                dep_key,
+                if expand || graph.dependencies(dep_key).is_empty() {
+                    ""
+                } else {
+                    " (deduped)"
+                }
+            ));
+            if expand {
+                let indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+                draw(graph, dep_key, &indent, drawn, out);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let mut drawn = HashSet::new();
+    for workspace in workspaces {
+        if graph.dependents(&workspace.key).is_empty() {
+            out.push_str(&format!("{}\n", workspace.key));
+            drawn.insert(workspace.key.clone());
+            draw(graph, &workspace.key, "", &mut drawn, &mut out);
+        }
+    }
+    // packages that only depend on each other in a cycle have no roots
+    for workspace in workspaces {
+        if drawn.insert(workspace.key.clone()) {
+            out.push_str(&format!("{}\n", workspace.key));
+            draw(graph, &workspace.key, "", &mut drawn, &mut out);
+        }
+    }
+    out
+}
+
+pub fn workspaces(project: &Project, tree_view: bool, json: bool) -> anyhow::Result<()> {
+    let graph = WorkspaceGraph::new(project);
+    let workspaces = find_workspaces(project, &graph);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&workspaces)?);
+    } else if tree_view {
+        print!("{}", tree(&graph, &workspaces));
+    } else {
+        for workspace in &workspaces {
+            println!(
+                "{}{}{}{}",
+                workspace.key,
+                match &workspace.version {
+                    Some(version) => format!("@{}", version),
+                    None => String::new(),
+                },
+                // the key of a package without a name is already its path
+                match &workspace.name {
+                    Some(_) => format!("  {}", workspace.path),
+                    None => String::new(),
+                },
+                if workspace.private { "  (private)" } else { "" }
+            );
+            if !workspace.dependencies.is_empty() {
+                let deps: Vec<_> = workspace
+                    .dependencies
+                    .iter()
+                    .map(|dep| dep.to_string())
+                    .collect();
+                println!("  depends on {}", deps.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn fixture_workspaces() {
+        let mut dir = env::current_dir().unwrap();
+        dir.push("fixtures/npm");
+        let project = Project::find(&dir, None).unwrap();
+        let graph = WorkspaceGraph::new(&project);
+        let workspaces = find_workspaces(&project, &graph);
+        let app = PackageName::new("@fixture/app".to_owned()).unwrap();
+        let lib = PackageName::new("@fixture/lib".to_owned()).unwrap();
+        assert_eq!(
+            workspaces[0],
+            Workspace {
+                key: PackageKey::Name(app.clone()),
+                name: Some(app),
+                version: Some("1.0.0".to_owned()),
+                path: "packages/app".to_owned(),
+                private: true,
+                dependencies: vec![PackageKey::Name(lib)],
+            }
+        );
+        assert_eq!(workspaces[2].path, ".");
+        assert_eq!(
+            tree(&graph, &workspaces),
+            "@fixture/app\n└── @fixture/lib\nfixture\n"
+        );
+    }
+}